@@ -0,0 +1,238 @@
+use crate::{
+    prelude::*,
+    shape::{Outline, Shape},
+    spiro::{Fixed, Line, Radius, Rotation},
+};
+use bevy::{
+    input::{
+        gestures::PinchGesture,
+        mouse::{MouseScrollUnit, MouseWheel},
+    },
+    window::PrimaryWindow,
+};
+use bevy_egui::EguiContexts;
+
+/// Zoom factor per line scrolled with a mouse wheel
+const WHEEL_ZOOM: f32 = 1.1;
+
+/// Pixels scrolled on a trackpad that count as one line of a mouse wheel
+const PIXELS_PER_LINE: f32 = 50.0;
+
+/// Smallest and largest world units per pixel
+const SCALE: (f32, f32) = (0.01, 100.0);
+
+/// Speed of panning with the arrow keys, in pixels per second
+const KEY_PAN_SPEED: f32 = 600.0;
+
+/// Room left around everything when fitting it on screen, as a fraction of its size
+const FIT_MARGIN: f32 = 0.05;
+
+pub(super) fn plugin(app: &mut App) {
+    app //
+        .add_systems(Startup, spawn_camera)
+        .add_systems(Update, (zoom, pan, keys))
+        .observe(fit_all)
+        .observe(reset_view);
+}
+
+/// Zoom and pan the camera so that every gear and line is in view
+#[derive(Event)]
+pub struct FitAll;
+
+/// Move the camera back to the origin at its original zoom
+#[derive(Event)]
+pub struct ResetView;
+
+fn spawn_camera(mut commands: Commands) {
+    commands.spawn(Camera2dBundle::default());
+}
+
+/// Scale the camera by `factor`, keeping the world point under `anchor` in place
+fn zoom_at(
+    transform: &mut Transform,
+    projection: &mut OrthographicProjection,
+    window: &Window,
+    anchor: Vec2,
+    factor: f32,
+) {
+    // Offset of the anchor from the center of the window, with y pointing up like the world
+    let offset = (anchor - 0.5 * window.size()) * Vec2::new(1.0, -1.0);
+
+    let scale = (projection.scale * factor).clamp(SCALE.0, SCALE.1);
+    let world = transform.translation.xy() + offset * projection.scale;
+    let translation = world - offset * scale;
+
+    projection.scale = scale;
+    transform.translation = translation.extend(transform.translation.z);
+}
+
+fn zoom(
+    mut wheel: EventReader<MouseWheel>,
+    mut pinch: EventReader<PinchGesture>,
+    mut q_camera: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+    q_primary_window: Query<&Window, With<PrimaryWindow>>,
+    mut contexts: EguiContexts,
+) {
+    let lines: f32 = wheel
+        .read()
+        .map(|event| match event.unit {
+            MouseScrollUnit::Line => event.y,
+            MouseScrollUnit::Pixel => event.y / PIXELS_PER_LINE,
+        })
+        .sum();
+    let pinched: f32 = pinch.read().map(|PinchGesture(delta)| delta).sum();
+    if lines == 0.0 && pinched == 0.0 {
+        return;
+    }
+
+    // Leave scrolling over the sidebar to the sidebar
+    if contexts.ctx_mut().is_pointer_over_area() {
+        return;
+    }
+
+    let window = r!(q_primary_window.get_single());
+    let anchor = rq!(window.cursor_position());
+    let (mut transform, mut projection) = r!(q_camera.get_single_mut());
+
+    let factor = WHEEL_ZOOM.powf(-lines) / (1.0 + pinched).max(0.1);
+    zoom_at(&mut transform, &mut projection, window, anchor, factor);
+}
+
+/// Drag the canvas around with the middle or right button, or the left one while holding space
+fn pan(
+    mut last: Local<Option<Vec2>>,
+    mouse: Res<ButtonInput<MouseButton>>,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut q_camera: Query<(&mut Transform, &OrthographicProjection), With<Camera>>,
+    q_primary_window: Query<&Window, With<PrimaryWindow>>,
+    mut contexts: EguiContexts,
+) {
+    let window = r!(q_primary_window.get_single());
+    let cursor = window.cursor_position();
+
+    let buttons = [MouseButton::Middle, MouseButton::Right];
+    let space = keys.pressed(KeyCode::Space);
+    let panning = mouse.any_pressed(buttons) || (space && mouse.pressed(MouseButton::Left));
+    let started =
+        mouse.any_just_pressed(buttons) || (space && mouse.just_pressed(MouseButton::Left));
+
+    // Only pans that start on the canvas count
+    if !panning || (started && contexts.ctx_mut().is_pointer_over_area()) {
+        *last = None;
+        return;
+    }
+    if started {
+        *last = cursor;
+        return;
+    }
+
+    let (Some(previous), Some(cursor)) = (*last, cursor) else {
+        return;
+    };
+    *last = Some(cursor);
+
+    let (mut transform, projection) = r!(q_camera.get_single_mut());
+    let delta = (cursor - previous) * Vec2::new(-1.0, 1.0) * projection.scale;
+    transform.translation += delta.extend(0.0);
+}
+
+fn keys(
+    mut commands: Commands,
+    keys: Res<ButtonInput<KeyCode>>,
+    mut q_camera: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+    q_primary_window: Query<&Window, With<PrimaryWindow>>,
+    mut contexts: EguiContexts,
+    time: Res<Time>,
+) {
+    // Leave the keys to text fields being typed in
+    if contexts.ctx_mut().wants_keyboard_input() {
+        return;
+    }
+
+    if keys.just_pressed(KeyCode::KeyF) {
+        commands.trigger(FitAll);
+    }
+    if keys.just_pressed(KeyCode::Home) {
+        commands.trigger(ResetView);
+    }
+
+    let window = r!(q_primary_window.get_single());
+    let (mut transform, mut projection) = r!(q_camera.get_single_mut());
+
+    let center = 0.5 * window.size();
+    if keys.any_just_pressed([KeyCode::Equal, KeyCode::NumpadAdd]) {
+        zoom_at(
+            &mut transform,
+            &mut projection,
+            window,
+            center,
+            1.0 / WHEEL_ZOOM,
+        );
+    }
+    if keys.any_just_pressed([KeyCode::Minus, KeyCode::NumpadSubtract]) {
+        zoom_at(&mut transform, &mut projection, window, center, WHEEL_ZOOM);
+    }
+
+    let direction = [
+        (KeyCode::ArrowLeft, Vec2::NEG_X),
+        (KeyCode::ArrowRight, Vec2::X),
+        (KeyCode::ArrowDown, Vec2::NEG_Y),
+        (KeyCode::ArrowUp, Vec2::Y),
+    ]
+    .iter()
+    .filter(|&&(key, _)| keys.pressed(key))
+    .map(|&(_, direction)| direction)
+    .sum::<Vec2>();
+    let delta = direction * KEY_PAN_SPEED * projection.scale * time.delta_seconds();
+    transform.translation += delta.extend(0.0);
+}
+
+fn fit_all(
+    _trigger: Trigger<FitAll>,
+    mut q_camera: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+    q_primary_window: Query<&Window, With<PrimaryWindow>>,
+    q_fixed: Query<(&Transform, &Shape), (With<Fixed>, Without<Camera>)>,
+    q_rotating: Query<(&Transform, &Radius), (With<Rotation>, Without<Camera>)>,
+    q_lines: Query<&Line>,
+) {
+    let fixed = q_fixed.iter().flat_map(|(transform, shape)| {
+        let center = transform.translation.xy();
+        shape
+            .outline()
+            .polyline()
+            .into_iter()
+            .map(move |p| center + p)
+    });
+    let rotating = q_rotating.iter().flat_map(|(transform, &Radius(radius))| {
+        Outline::new(vec![transform.translation.xy()], radius).polyline()
+    });
+    let lines = q_lines
+        .iter()
+        .flat_map(|Line(points)| points.iter().copied());
+
+    let bounds = rq!(fixed
+        .chain(rotating)
+        .chain(lines)
+        .map(|p| Rect::from_corners(p, p))
+        .reduce(|a, b| a.union(b)));
+
+    let window = r!(q_primary_window.get_single());
+    let (mut transform, mut projection) = r!(q_camera.get_single_mut());
+
+    let scale = (bounds.size() * (1.0 + 2.0 * FIT_MARGIN) / window.size()).max_element();
+    projection.scale = if scale > 0.0 {
+        scale.clamp(SCALE.0, SCALE.1)
+    } else {
+        1.0
+    };
+    transform.translation = bounds.center().extend(transform.translation.z);
+}
+
+fn reset_view(
+    _trigger: Trigger<ResetView>,
+    mut q_camera: Query<(&mut Transform, &mut OrthographicProjection), With<Camera>>,
+) {
+    let (mut transform, mut projection) = r!(q_camera.get_single_mut());
+    projection.scale = 1.0;
+    transform.translation = Vec3::new(0.0, 0.0, transform.translation.z);
+}
@@ -1,9 +1,24 @@
-use crate::{prelude::*, spiro::Radius, ui::Cursor};
+use crate::{
+    prelude::*,
+    shape::{distance_to_segment, Shape},
+    spiro::{
+        Closure, Fixed, Line, LineColor, Pen, PenAngle, PenPos, Radius, Rotation, Settings, Side,
+        Teeth, Trace,
+    },
+    ui::Cursor,
+};
 use bevy::{
-    input::common_conditions::{input_just_pressed, input_just_released},
+    input::common_conditions::{input_just_pressed, input_just_released, input_pressed},
     window::PrimaryWindow,
 };
-use bevy_egui::egui::CursorIcon;
+use bevy_egui::{egui::CursorIcon, EguiContexts};
+use std::f32::consts::TAU;
+
+/// How close to a pen or the rim of a selected gear the cursor grabs it, in pixels
+const REACH: f32 = 8.0;
+
+/// Most points drawn for the preview of a single curve
+const PREVIEW_POINTS: f32 = 20_000.0;
 
 pub(super) fn plugin(app: &mut App) {
     app //
@@ -14,10 +29,16 @@ pub(super) fn plugin(app: &mut App) {
                 get_cursor_world_pos,
                 (
                     hovered,
-                    start_drag.run_if(input_just_pressed(MouseButton::Left)),
+                    // Space turns the left button into panning the canvas
+                    (select, start_drag).chain().run_if(
+                        input_just_pressed(MouseButton::Left)
+                            .and_then(not(input_pressed(KeyCode::Space))),
+                    ),
                     end_drag.run_if(input_just_released(MouseButton::Left)),
-                    drag.run_if(resource_exists::<DragOperation>),
+                    (drag, drag_rim, drag_pen, drag_roll).run_if(resource_exists::<DragOperation>),
                     cursor,
+                    draw_handles,
+                    preview.run_if(resource_exists::<DragOperation>),
                 ),
             )
                 .chain(),
@@ -32,12 +53,39 @@ struct CursorWorldPos(Option<Vec2>);
 #[derive(Resource)]
 struct DragOperation {
     offset: Vec2,
+    grip: Grip,
+    /// Where the cursor grabbed the entity, nothing changes until it moves away
+    start: Vec2,
+    /// Rotation of a rolled gear when grabbed, and the rotation recovered from where it was
+    /// grabbed, rolling turns it by how far the recovered rotation moves from the latter
+    roll: Option<(f32, f32)>,
+}
+
+impl DragOperation {
+    fn moved(&self, cursor_world_pos: Vec2) -> bool {
+        cursor_world_pos != self.start
+    }
 }
 
 #[derive(Resource)]
 struct Hovered {
     offset: Vec2,
     entity: Entity,
+    grip: Grip,
+}
+
+/// The part of an entity that is grabbed
+#[derive(Clone, Copy, PartialEq)]
+enum Grip {
+    /// The whole gear, moving it around
+    Body,
+    /// The rim of a selected gear, resizing it. Holds how far from the center of the gear it was
+    /// grabbed and the perimeter of the gear at the time
+    Rim { distance: f32, perimeter: f32 },
+    /// A pen, moving it around on its gear
+    Pen,
+    /// A rotating gear, rolling it along the gear it rolls on
+    Roll,
 }
 
 #[derive(Component)]
@@ -46,6 +94,10 @@ pub struct Draggable;
 #[derive(Component)]
 pub struct Dragged;
 
+/// A gear showing handles to resize it and move its pens
+#[derive(Component)]
+pub struct Selected;
+
 #[derive(Event)]
 pub struct DragStart;
 
@@ -66,22 +118,101 @@ fn get_cursor_world_pos(
         .and_then(|cursor_pos| main_camera.viewport_to_world_2d(main_camera_transform, cursor_pos));
 }
 
+#[allow(clippy::too_many_arguments)]
 fn hovered(
     mut commands: Commands,
     cursor_world_pos: Res<CursorWorldPos>,
-    q_draggable: Query<(Entity, &Transform, &Radius), With<Draggable>>,
+    q_draggable: Query<(Entity, &Transform, &Shape), With<Draggable>>,
+    q_draggable_rotating: Query<(Entity, &Transform, &Radius), (With<Draggable>, With<Rotation>)>,
+    q_selected_fixed: Query<(Entity, &Transform, &Shape), (With<Selected>, With<Fixed>)>,
+    q_selected_rotating: Query<(Entity, &Transform, &Radius), (With<Selected>, With<Rotation>)>,
+    q_pens: Query<(Entity, &PenPos, &Parent)>,
+    q_projection: Query<&OrthographicProjection, With<Camera>>,
+    settings: Res<Settings>,
 ) {
     // If the cursor is not within the primary window skip this system
     let cursor_world_pos = rq!(cursor_world_pos.0);
+    let reach = REACH * r!(q_projection.get_single()).scale;
+
+    // Pens sit on top of the gears carrying them, so they are picked first, but only while they
+    // are shown
+    for (entity, &PenPos(pos), parent) in &q_pens {
+        let shown = settings.gizmos_enabled || q_selected_rotating.contains(parent.get());
+        if shown && pos.distance(cursor_world_pos) < reach {
+            let offset = pos - cursor_world_pos;
+            commands.insert_resource(Hovered {
+                offset,
+                entity,
+                grip: Grip::Pen,
+            });
+            return;
+        }
+    }
 
-    for (entity, transform, &Radius(radius)) in &q_draggable {
+    // Then the rims of the selected gears
+    for (entity, transform, &Radius(radius)) in &q_selected_rotating {
+        let distance = transform.translation.xy().distance(cursor_world_pos);
+        if (distance - radius).abs() < reach {
+            commands.insert_resource(Hovered {
+                offset: Vec2::ZERO,
+                entity,
+                grip: Grip::Rim {
+                    distance,
+                    perimeter: TAU * radius,
+                },
+            });
+            return;
+        }
+    }
+    for (entity, transform, shape) in &q_selected_fixed {
+        let outline = shape.outline();
+        let point = cursor_world_pos - transform.translation.xy();
+        let to_rim = outline
+            .polyline()
+            .windows(2)
+            .map(|edge| distance_to_segment(point, edge[0], edge[1]))
+            .fold(f32::INFINITY, f32::min);
+        if to_rim < reach {
+            commands.insert_resource(Hovered {
+                offset: Vec2::ZERO,
+                entity,
+                grip: Grip::Rim {
+                    distance: point.length(),
+                    perimeter: outline.perimeter(),
+                },
+            });
+            return;
+        }
+    }
+
+    // Then the rotating gears, the smallest one under the cursor being on top
+    let rotating = q_draggable_rotating
+        .iter()
+        .filter(|(_, transform, Radius(radius))| {
+            transform.translation.xy().distance(cursor_world_pos) < *radius
+        })
+        .min_by(|(_, _, a), (_, _, b)| a.0.total_cmp(&b.0));
+    if let Some((entity, transform, _radius)) = rotating {
+        commands.insert_resource(Hovered {
+            offset: transform.translation.xy() - cursor_world_pos,
+            entity,
+            grip: Grip::Roll,
+        });
+        return;
+    }
+
+    for (entity, transform, shape) in &q_draggable {
         // Get the offset from the cursor to transform
         let offset = transform.translation.truncate() - cursor_world_pos;
 
-        // If the cursor is within the cricle the drag hovered operation and remember the offset of the
+        // If the cursor is within the shape the drag hovered operation and remember the offset of the
         // cursor from the origin
-        if offset.length() < radius {
-            commands.insert_resource(Hovered { offset, entity });
+        if shape.outline().contains(-offset) {
+            commands.insert_resource(Hovered {
+                offset,
+                entity,
+                grip: Grip::Body,
+            });
             break;
         } else {
             commands.remove_resource::<Hovered>();
@@ -89,12 +220,72 @@ fn hovered(
     }
 }
 
+/// Select the gear clicked on to show its handles, or none when clicking on the empty canvas
+fn select(
+    mut commands: Commands,
+    cursor_world_pos: Res<CursorWorldPos>,
+    hovered: Option<Res<Hovered>>,
+    q_selected: Query<Entity, With<Selected>>,
+    q_fixed: Query<(Entity, &Transform, &Shape), With<Fixed>>,
+    q_rotating: Query<(Entity, &Transform, &Radius), With<Rotation>>,
+    mut contexts: EguiContexts,
+) {
+    // Clicks on the sidebar are none of the canvas' business
+    if contexts.ctx_mut().is_pointer_over_area() {
+        return;
+    }
+
+    // Grabbing one of the handles keeps the gear selected, grabbing a gear selects it
+    if hovered.is_some_and(|hovered| matches!(hovered.grip, Grip::Rim { .. } | Grip::Pen)) {
+        return;
+    }
+
+    let cursor_world_pos = rq!(cursor_world_pos.0);
+
+    // The smallest rotating gear under the cursor is the one on top, and rotating gears are on top
+    // of fixed ones
+    let rotating = q_rotating
+        .iter()
+        .filter(|(_, transform, Radius(radius))| {
+            transform.translation.xy().distance(cursor_world_pos) < *radius
+        })
+        .min_by(|(_, _, a), (_, _, b)| a.0.total_cmp(&b.0))
+        .map(|(entity, ..)| entity);
+    let selected = rotating.or_else(|| {
+        q_fixed
+            .iter()
+            .find(|(_, transform, shape)| {
+                let point = cursor_world_pos - transform.translation.xy();
+                shape.outline().contains(point)
+            })
+            .map(|(entity, ..)| entity)
+    });
+
+    for entity in &q_selected {
+        if Some(entity) != selected {
+            commands.entity(entity).remove::<Selected>();
+        }
+    }
+    if let Some(entity) = selected {
+        commands.entity(entity).insert(Selected);
+    }
+}
+
 // Start the drag operation and record the offset we started dragging from
-fn start_drag(mut commands: Commands, hovered: Option<Res<Hovered>>) {
+fn start_drag(
+    mut commands: Commands,
+    hovered: Option<Res<Hovered>>,
+    cursor_world_pos: Res<CursorWorldPos>,
+) {
+    let cursor_world_pos = rq!(cursor_world_pos.0);
+
     // If hovered, start the drag operation and remember the offset of the cursor from the origin
     if let Some(hovered) = &hovered {
         commands.insert_resource(DragOperation {
             offset: hovered.offset,
+            grip: hovered.grip,
+            start: cursor_world_pos,
+            roll: None,
         });
         commands.entity(hovered.entity).insert(Dragged);
         commands.trigger_targets(DragStart, hovered.entity);
@@ -115,6 +306,10 @@ fn drag(
     cursor_world_pos: Res<CursorWorldPos>,
     mut q_draggable: Query<&mut Transform, With<Dragged>>,
 ) {
+    if drag_operation.grip != Grip::Body {
+        return;
+    }
+
     // If the cursor is not within the primary window skip this system
     let cursor_world_pos = rq!(cursor_world_pos.0);
 
@@ -125,16 +320,370 @@ fn drag(
     transform.translation = new_translation.extend(transform.translation.z);
 }
 
+/// Resize a gear by its rim, scaling it by how much further from its center the cursor is than
+/// where the rim was grabbed. Gears with teeth get as many teeth as fit the new size instead.
+fn drag_rim(
+    drag_operation: Res<DragOperation>,
+    cursor_world_pos: Res<CursorWorldPos>,
+    mut q_fixed: Query<
+        (Entity, &Transform, &mut Shape, Option<&mut Teeth>),
+        (With<Dragged>, With<Fixed>),
+    >,
+    mut q_rotating: Query<
+        (Entity, &Transform, &mut Radius, Option<&mut Teeth>),
+        (With<Dragged>, With<Rotation>, Without<Fixed>),
+    >,
+    q_children: Query<&Children>,
+    mut q_lines: Query<&mut Line, With<Pen>>,
+    settings: Res<Settings>,
+) {
+    let Grip::Rim {
+        distance,
+        perimeter,
+    } = drag_operation.grip
+    else {
+        return;
+    };
+
+    // If the cursor is not within the primary window skip this system, and leave the gear alone
+    // until the cursor moves
+    let cursor_world_pos = rq!(cursor_world_pos.0);
+    if !drag_operation.moved(cursor_world_pos) {
+        return;
+    }
+    let scale = |center: Vec2| cursor_world_pos.distance(center) / distance.max(f32::EPSILON);
+
+    if let Ok((entity, transform, mut shape, teeth)) = q_fixed.get_single_mut() {
+        let target = perimeter * scale(transform.translation.xy());
+        let resized = match teeth {
+            Some(mut teeth) => {
+                let count = ((target / settings.teeth_perimeter(Teeth(1))).round() as u32).max(1);
+                let resized = count != teeth.0;
+                teeth.0 = count;
+                resized
+            }
+            None => {
+                let current = shape.outline().perimeter();
+                let resized = current > 0.0 && target > 0.0 && target != current;
+                if resized {
+                    *shape = shape.scaled(target / current);
+                }
+                resized
+            }
+        };
+
+        // Every gear rolling around the fixed one draws along its rim
+        if resized {
+            clear_lines(entity, &q_children, &mut q_lines);
+        }
+    }
+
+    if let Ok((entity, transform, mut radius, teeth)) = q_rotating.get_single_mut() {
+        let target = perimeter / TAU * scale(transform.translation.xy());
+        let resized = match teeth {
+            Some(mut teeth) => {
+                let count = ((target / settings.teeth_radius(Teeth(1))).round() as u32).max(1);
+                let resized = count != teeth.0;
+                teeth.0 = count;
+                resized
+            }
+            None => {
+                let resized = target != radius.0;
+                radius.0 = target;
+                resized
+            }
+        };
+
+        if resized {
+            clear_lines(entity, &q_children, &mut q_lines);
+        }
+    }
+}
+
+/// Move a dragged pen to the cursor, in polar coordinates around the center of its gear
+fn drag_pen(
+    drag_operation: Res<DragOperation>,
+    cursor_world_pos: Res<CursorWorldPos>,
+    mut q_pens: Query<(&mut Pen, &mut PenAngle, &Parent), With<Dragged>>,
+    q_rotating: Query<&Transform, With<Rotation>>,
+) {
+    if drag_operation.grip != Grip::Pen {
+        return;
+    }
+
+    // If the cursor is not within the primary window skip this system
+    let cursor_world_pos = rq!(cursor_world_pos.0);
+
+    let (mut pen, mut pen_angle, parent) = r!(q_pens.get_single_mut());
+    let rotating_transform = r!(q_rotating.get(parent.get()));
+
+    // Undo the turn of the gear to get back to where the pen sits on it
+    let angle = rotating_transform.rotation.to_euler(EulerRot::XYZ).2;
+    let offset = Vec2::from_angle(-angle)
+        .rotate(cursor_world_pos + drag_operation.offset - rotating_transform.translation.xy());
+
+    pen.0 = offset.length();
+    pen_angle.0 = Vec2::Y.angle_between(offset);
+}
+
+/// Roll a dragged rotating gear to where its center is closest to the cursor, turning it as far as
+/// rolling there takes
+fn drag_roll(
+    mut drag_operation: ResMut<DragOperation>,
+    cursor_world_pos: Res<CursorWorldPos>,
+    mut q_rotating: Query<(Entity, &mut Rotation, &Radius, &Side, &Parent), With<Dragged>>,
+    q_carriers: Query<(&Transform, Option<&Shape>, Option<&Radius>)>,
+    q_children: Query<&Children>,
+    mut q_lines: Query<&mut Line, With<Pen>>,
+) {
+    if drag_operation.grip != Grip::Roll {
+        return;
+    }
+
+    // If the cursor is not within the primary window skip this system, and leave the gear and its
+    // lines alone until the cursor moves
+    let cursor_world_pos = rq!(cursor_world_pos.0);
+    if !drag_operation.moved(cursor_world_pos) {
+        return;
+    }
+
+    let (entity, mut rotation, &Radius(radius), &side, parent) = r!(q_rotating.get_single_mut());
+    let (carrier_transform, shape, carrier_radius) = r!(q_carriers.get(parent.get()));
+    let shape = match (shape, carrier_radius) {
+        (Some(shape), _) => shape.clone(),
+        (None, Some(&Radius(radius))) => Shape::Ring { radius },
+        (None, None) => return,
+    };
+    let trace = Trace::new(&shape, radius, side);
+
+    // Where the center of the gear goes for a cursor position, relative to the gear it rolls on
+    let angle = carrier_transform.rotation.to_euler(EulerRot::XYZ).2;
+    let offset = drag_operation.offset - carrier_transform.translation.xy();
+    let center = |cursor: Vec2| Vec2::from_angle(-angle).rotate(cursor + offset);
+
+    // Recovering a rotation from a position loses precision on gears that have turned a lot, so
+    // the gear turns by the difference between two recovered rotations rather than to one
+    let start = drag_operation.start;
+    let (grabbed, recovered) = *drag_operation
+        .roll
+        .get_or_insert_with(|| (rotation.0, trace.rotation_at(center(start), rotation.0)));
+    let target = trace.rotation_at(center(cursor_world_pos), recovered + rotation.0 - grabbed);
+    let rolled = grabbed + (target - recovered);
+
+    if rolled != rotation.0 {
+        rotation.0 = rolled;
+        clear_lines(entity, &q_children, &mut q_lines);
+    }
+}
+
+/// Start new curves for the pens of a rotating gear rolled or resized by hand, and for those of the
+/// gears rolling on it, rather than joining up with the old ones
+fn clear_lines(
+    gear: Entity,
+    q_children: &Query<&Children>,
+    q_lines: &mut Query<&mut Line, With<Pen>>,
+) {
+    for pen in q_children.iter_descendants(gear) {
+        if let Ok(mut line) = q_lines.get_mut(pen) {
+            line.0 = Vec::new();
+        }
+    }
+}
+
 fn cursor(
     mut cursor: ResMut<Cursor>,
     drag: Option<Res<DragOperation>>,
     hovered: Option<Res<Hovered>>,
 ) {
-    cursor.0 = if drag.is_some() {
-        Some(CursorIcon::Grabbing)
-    } else if hovered.is_some() {
-        Some(CursorIcon::Grab)
+    let grip = drag
+        .map(|drag| (drag.grip, true))
+        .or(hovered.map(|hovered| (hovered.grip, false)));
+
+    cursor.0 = match grip {
+        Some((Grip::Rim { .. }, _)) => Some(CursorIcon::ResizeNwSe),
+        Some((_, true)) => Some(CursorIcon::Grabbing),
+        Some((_, false)) => Some(CursorIcon::Grab),
+        None => None,
+    };
+}
+
+/// Outline the selected gears, with a handle on each pen of the rotating ones
+fn draw_handles(
+    mut gizmos: Gizmos,
+    q_fixed: Query<(&Transform, &Shape), (With<Selected>, With<Fixed>)>,
+    q_rotating: Query<(&Transform, &Radius, Option<&Children>), (With<Selected>, With<Rotation>)>,
+    q_pens: Query<&PenPos>,
+    q_projection: Query<&OrthographicProjection, With<Camera>>,
+) {
+    let reach = REACH * r!(q_projection.get_single()).scale;
+
+    for (transform, shape) in &q_fixed {
+        let center = transform.translation.xy();
+        gizmos.linestrip_2d(
+            shape.outline().polyline().into_iter().map(|p| center + p),
+            color::SKY_400,
+        );
+    }
+
+    for (transform, &Radius(radius), children) in &q_rotating {
+        let center = transform.translation.xy();
+        gizmos.circle_2d(center, radius, color::SKY_400);
+
+        for &PenPos(pos) in q_pens.iter_many(children.into_iter().flatten()) {
+            gizmos.line_2d(center, pos, color::SKY_400);
+            gizmos.circle_2d(pos, reach, color::SKY_400);
+        }
+    }
+}
+
+/// Show the whole curves the gear whose rim or pen is dragged will draw, as far as they are known
+/// for gears rolling on a fixed gear
+fn preview(
+    mut gizmos: Gizmos,
+    drag_operation: Res<DragOperation>,
+    q_dragged: Query<Entity, With<Dragged>>,
+    q_fixed: Query<(&Transform, &Shape, Option<&Teeth>, Option<&Children>), With<Fixed>>,
+    q_rotating: Query<
+        (
+            &Rotation,
+            &Radius,
+            &Side,
+            Option<&Teeth>,
+            &Parent,
+            Option<&Children>,
+        ),
+        Without<Fixed>,
+    >,
+    q_pens: Query<(&Pen, &PenAngle, &LineColor, &Parent)>,
+    settings: Res<Settings>,
+) {
+    if drag_operation.grip == Grip::Body {
+        return;
+    }
+
+    let dragged = r!(q_dragged.get_single());
+
+    // The gears changed by the drag: that of a pen, a resized rotating gear, or those rolling on a
+    // resized fixed gear
+    let gears = if let Ok((.., gear)) = q_pens.get(dragged) {
+        vec![gear.get()]
+    } else if let Ok((.., children)) = q_fixed.get(dragged) {
+        children.into_iter().flatten().copied().collect()
     } else {
-        None
+        vec![dragged]
     };
+
+    for (rotation, &Radius(radius), &side, teeth, parent, children) in q_rotating.iter_many(gears) {
+        let (transform, shape, fixed_teeth, _) = c!(q_fixed.get(parent.get()));
+        let origin = transform.translation.xy();
+
+        let trace = Trace::new(shape, radius, side);
+        let closure = c!(fixed_teeth
+            .zip(teeth)
+            .and_then(|(&fixed_teeth, &teeth)| Closure::new(&trace, fixed_teeth, teeth))
+            .or_else(|| Closure::estimate(&trace)));
+
+        // Keep long patterns cheap enough to draw every frame
+        let resolution = (PREVIEW_POINTS * TAU / closure.rotation)
+            .clamp(1.0, settings.render_resolution as f32) as u32;
+
+        for (pen, &angle, line_color, _) in q_pens.iter_many(children.into_iter().flatten()) {
+            let curve = trace.curve(rotation.0, closure.rotation, resolution, pen.offset(angle));
+            gizmos.linestrip_gradient_2d(
+                curve
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, p)| (origin + p, line_color.at(i).with_alpha(0.5))),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    #[test]
+    fn clicking_a_gear_that_turned_a_lot_keeps_its_lines() {
+        let ring = Shape::Ring { radius: 150.0 };
+        let rotation = 5000.0;
+        let (_angle, center) = Trace::new(&ring, 50.0, Side::Inside).angle_and_center(rotation);
+        let cursor = center + Vec2::new(3.0, 4.0);
+
+        let mut world = World::new();
+        world.insert_resource(CursorWorldPos(Some(cursor)));
+        world.insert_resource(DragOperation {
+            offset: center - cursor,
+            grip: Grip::Roll,
+            start: cursor,
+            roll: None,
+        });
+        let (mut gear, mut pen) = (None, None);
+        world
+            .spawn((Transform::default(), ring))
+            .with_children(|parent| {
+                let mut entity =
+                    parent.spawn((Rotation(rotation), Radius(50.0), Side::Inside, Dragged));
+                entity.with_children(|parent| {
+                    pen = Some(
+                        parent
+                            .spawn((Pen(10.0), Line(vec![Vec2::ZERO, Vec2::ONE])))
+                            .id(),
+                    );
+                });
+                gear = Some(entity.id());
+            });
+        let (gear, pen) = (gear.unwrap(), pen.unwrap());
+
+        world.run_system_once(drag_roll);
+        assert_eq!(world.get::<Rotation>(gear).unwrap().0, rotation);
+        assert_eq!(world.get::<Line>(pen).unwrap().0.len(), 2);
+
+        // Moving the cursor rolls the gear a little from where it was and starts new lines
+        world.resource_mut::<CursorWorldPos>().0 = Some(cursor + Vec2::new(0.0, 5.0));
+        world.run_system_once(drag_roll);
+        let rolled = world.get::<Rotation>(gear).unwrap().0;
+        assert!(rolled != rotation && (rolled - rotation).abs() < 1.0);
+        assert!(world.get::<Line>(pen).unwrap().0.is_empty());
+    }
+
+    #[test]
+    fn resizing_the_fixed_gear_clears_the_lines_around_it() {
+        let start = Vec2::new(150.0, 0.0);
+
+        let mut world = World::new();
+        world.insert_resource(Settings::default());
+        world.insert_resource(CursorWorldPos(Some(start + Vec2::new(15.0, 0.0))));
+        world.insert_resource(DragOperation {
+            offset: Vec2::ZERO,
+            grip: Grip::Rim {
+                distance: 150.0,
+                perimeter: TAU * 150.0,
+            },
+            start,
+            roll: None,
+        });
+        let mut pen = None;
+        let fixed = world
+            .spawn((
+                Transform::default(),
+                Shape::Ring { radius: 150.0 },
+                Fixed,
+                Dragged,
+            ))
+            .with_children(|parent| {
+                parent
+                    .spawn((Rotation(0.0), Radius(50.0), Side::Inside))
+                    .with_children(|parent| {
+                        pen = Some(parent.spawn((Pen(10.0), Line(vec![Vec2::ZERO]))).id());
+                    });
+            })
+            .id();
+
+        world.run_system_once(drag_rim);
+        let perimeter = world.get::<Shape>(fixed).unwrap().outline().perimeter();
+        assert!((perimeter - TAU * 165.0).abs() < 1.0);
+        assert!(world.get::<Line>(pen.unwrap()).unwrap().0.is_empty());
+    }
 }
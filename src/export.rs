@@ -0,0 +1,405 @@
+use crate::{
+    file,
+    prelude::*,
+    shape::{simplify, Outline, Shape},
+    spiro::{Fixed, GearColor, Line, LineColor, PenStyle, PenWidth, Radius, Rotation, Settings},
+    stroke::{Cap, Join},
+};
+use bevy::math::Affine2;
+
+mod gcode;
+mod hpgl;
+mod optimize;
+mod png;
+mod svg;
+
+/// Space around the drawing in world units
+const MARGIN: f32 = 10.0;
+
+pub(super) fn plugin(app: &mut App) {
+    app //
+        .insert_resource(ExportSettings::default())
+        .insert_resource(ExportReport::default())
+        .observe(export);
+}
+
+#[derive(Resource)]
+pub struct ExportSettings {
+    /// Draw the outlines of the gears along with the lines
+    pub gears: bool,
+    /// Width of PNG images in pixels, the height follows from the drawing
+    pub png_width: u32,
+    /// Resolution stored in PNG images, to print them at the intended size
+    pub dpi: u32,
+    /// Leave the background of PNG images transparent instead of filling it with the clear color
+    pub transparent: bool,
+    /// Reorder, reverse and merge the polylines of vector exports to keep the pen down
+    pub optimize: bool,
+    /// Paper that plotter exports are placed on
+    pub page: Page,
+    pub gcode: GcodeSettings,
+    pub hpgl: HpglSettings,
+}
+
+impl Default for ExportSettings {
+    fn default() -> Self {
+        Self {
+            gears: false,
+            png_width: 4096,
+            dpi: 300,
+            transparent: false,
+            optimize: true,
+            page: Page::default(),
+            gcode: GcodeSettings::default(),
+            hpgl: HpglSettings::default(),
+        }
+    }
+}
+
+/// What the last vector export found out about the drawing
+#[derive(Resource, Default)]
+pub struct ExportReport {
+    /// Distance the pen travels lifted, in millimeters on the page, before and after optimizing
+    pub travel: Option<(f32, f32)>,
+}
+
+/// Where plotter exports end up on paper, in millimeters
+pub struct Page {
+    pub size: Vec2,
+    /// Blank space kept along every side of the page
+    pub margin: f32,
+    /// Millimeters per world unit, or `None` to fit the drawing inside the margins
+    pub scale: Option<f32>,
+}
+
+impl Default for Page {
+    fn default() -> Self {
+        // A4 in landscape
+        Self {
+            size: Vec2::new(297.0, 210.0),
+            margin: 10.0,
+            scale: None,
+        }
+    }
+}
+
+impl Page {
+    /// Map from world units to millimeters from the bottom left corner of the page, centering
+    /// `bounds` on the page
+    pub fn placement(&self, bounds: Rect) -> Affine2 {
+        let scale = self.scale.unwrap_or_else(|| {
+            let room = (self.size - 2.0 * self.margin).max(Vec2::ZERO) / bounds.size();
+            room.min_element()
+        });
+        let scale = if scale.is_finite() { scale } else { 1.0 };
+
+        Affine2::from_translation(0.5 * self.size)
+            * Affine2::from_scale(Vec2::splat(scale))
+            * Affine2::from_translation(-bounds.center())
+    }
+}
+
+/// Commands driving the plotter, which differ between firmwares
+pub struct GcodeSettings {
+    pub pen_up: String,
+    pub pen_down: String,
+    /// Sent before each color is plotted, to wait for its pen to be loaded
+    pub tool_change: String,
+    /// Drawing speed in millimeters per minute
+    pub feed_rate: f32,
+}
+
+impl Default for GcodeSettings {
+    fn default() -> Self {
+        Self {
+            pen_up: "G0 Z5".to_string(),
+            pen_down: "G1 Z0".to_string(),
+            tool_change: "M0".to_string(),
+            feed_rate: 3000.0,
+        }
+    }
+}
+
+/// How the page lines up with the axes of an HPGL plotter
+pub struct HpglSettings {
+    /// Position of the plotter's origin on the page, in millimeters from its bottom left corner
+    pub origin: Vec2,
+    /// Counterclockwise rotation of the plotter's axes relative to the page, in degrees
+    pub rotation: f32,
+    /// Pens the plotter holds, further colors reuse them from the first
+    pub pens: u32,
+}
+
+impl Default for HpglSettings {
+    fn default() -> Self {
+        Self {
+            origin: Vec2::ZERO,
+            rotation: 0.0,
+            pens: 8,
+        }
+    }
+}
+
+/// Save the current drawing in the given format
+#[derive(Event, Clone, Copy)]
+pub enum Export {
+    Svg,
+    Png,
+    Gcode,
+    Hpgl,
+}
+
+/// Everything that can be exported, in world coordinates
+pub struct Drawing {
+    pub background: Srgba,
+    pub lines: Vec<DrawnLine>,
+    pub gears: Vec<DrawnGear>,
+}
+
+pub struct DrawnLine {
+    pub points: Vec<Vec2>,
+    pub color: LineColor,
+    pub width: f32,
+    pub style: PenStyle,
+}
+
+impl DrawnLine {
+    /// Consecutive stretches of the line with the same color, sharing their end points
+    pub fn runs(&self) -> Vec<(Srgba, &[Vec2])> {
+        let mut runs = Vec::new();
+        let last = self.points.len() - 1;
+        let mut start = 0;
+        loop {
+            let color = self.color.at(start);
+            let end = (start + 1..last)
+                .find(|&i| self.color.at(i) != color)
+                .unwrap_or(last);
+
+            runs.push((color, &self.points[start..=end]));
+
+            if end == last {
+                return runs;
+            }
+            start = end;
+        }
+    }
+}
+
+pub struct DrawnGear {
+    /// Closed outline of the gear
+    pub outline: Vec<Vec2>,
+    pub color: Srgba,
+}
+
+/// Polylines drawn with the same color, to be plotted with one pen
+pub struct Pass {
+    pub color: Srgba,
+    pub paths: Vec<Polyline>,
+}
+
+#[derive(Clone)]
+pub struct Polyline {
+    pub points: Vec<Vec2>,
+    pub width: f32,
+    pub style: PenStyle,
+}
+
+impl Drawing {
+    /// The area covered by the drawing, including the width of its lines
+    pub fn bounds(&self) -> Rect {
+        let lines = self.lines.iter().flat_map(|line| {
+            let half_width = Vec2::splat(0.5 * line.width);
+            line.points
+                .iter()
+                .flat_map(move |&p| [p - half_width, p + half_width])
+        });
+        let gears = self
+            .gears
+            .iter()
+            .flat_map(|gear| gear.outline.iter().copied());
+
+        lines
+            .chain(gears)
+            .map(|p| Rect::from_corners(p, p))
+            .reduce(|a, b| a.union(b))
+            .unwrap_or_default()
+    }
+
+    /// The gears and lines as polylines grouped by color, in order of first appearance
+    pub fn passes(&self) -> Vec<Pass> {
+        let gears = self.gears.iter().map(|gear| {
+            // Outlines usually come closed already, see [`Outline::polyline`]
+            let mut points = gear.outline.clone();
+            if points.first() != points.last() {
+                points.extend(gear.outline.first());
+            }
+            let outline = Polyline {
+                points,
+                width: 1.0,
+                style: PenStyle {
+                    cap: Cap::Butt,
+                    join: Join::Miter,
+                },
+            };
+            (gear.color, outline)
+        });
+        let lines = self.lines.iter().flat_map(|line| {
+            line.runs().into_iter().map(move |(color, run)| {
+                let run = Polyline {
+                    points: run.to_vec(),
+                    width: line.width,
+                    style: line.style,
+                };
+                (color, run)
+            })
+        });
+
+        let mut passes: Vec<Pass> = Vec::new();
+        for (color, path) in gears.chain(lines) {
+            match passes.iter_mut().find(|pass| pass.color == color) {
+                Some(pass) => pass.paths.push(path),
+                None => passes.push(Pass {
+                    color,
+                    paths: vec![path],
+                }),
+            }
+        }
+        passes
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn export(
+    trigger: Trigger<Export>,
+    q_lines: Query<(&Line, &LineColor, &PenWidth, &PenStyle)>,
+    q_fixed: Query<(&Transform, &Shape, &GearColor), With<Fixed>>,
+    q_rotating: Query<(&Transform, &Radius, &GearColor), With<Rotation>>,
+    clear_color: Res<ClearColor>,
+    settings: Res<ExportSettings>,
+    spiro_settings: Res<Settings>,
+    mut report: ResMut<ExportReport>,
+) {
+    let lines = q_lines
+        .iter()
+        .filter(|(Line(points), ..)| !points.is_empty())
+        .map(
+            |(Line(points), &color, &PenWidth(width), &style)| DrawnLine {
+                points: points.clone(),
+                color,
+                width,
+                style,
+            },
+        )
+        .collect();
+
+    let mut gears = Vec::new();
+    if settings.gears {
+        for (transform, shape, &GearColor(color)) in &q_fixed {
+            let center = transform.translation.xy();
+            gears.push(DrawnGear {
+                outline: shape
+                    .outline()
+                    .polyline()
+                    .into_iter()
+                    .map(|p| center + p)
+                    .collect(),
+                color,
+            });
+        }
+
+        for (transform, &Radius(radius), &GearColor(color)) in &q_rotating {
+            let outline = Outline::new(vec![transform.translation.xy()], radius);
+            gears.push(DrawnGear {
+                outline: outline.polyline(),
+                color,
+            });
+        }
+    }
+
+    let drawing = Drawing {
+        background: clear_color.0.to_srgba(),
+        lines,
+        gears,
+    };
+
+    match trigger.event() {
+        Export::Svg => {
+            let passes = plotted_passes(
+                &drawing,
+                &settings,
+                spiro_settings.simplify_tolerance,
+                &mut report,
+            );
+            file::save(
+                "spiro.svg".to_string(),
+                svg::write(&drawing, &passes).into_bytes(),
+            );
+        }
+        Export::Png => match png::write(&drawing, &settings) {
+            Ok(bytes) => file::save("spiro.png".to_string(), bytes),
+            Err(err) => error!("Failed to export PNG: {err}"),
+        },
+        Export::Gcode => {
+            let passes = plotted_passes(
+                &drawing,
+                &settings,
+                spiro_settings.simplify_tolerance,
+                &mut report,
+            );
+            file::save(
+                "spiro.gcode".to_string(),
+                gcode::write(&drawing, &passes, &settings.page, &settings.gcode).into_bytes(),
+            );
+        }
+        Export::Hpgl => {
+            let passes = plotted_passes(
+                &drawing,
+                &settings,
+                spiro_settings.simplify_tolerance,
+                &mut report,
+            );
+            file::save(
+                "spiro.plt".to_string(),
+                hpgl::write(&drawing, &passes, &settings.page, &settings.hpgl).into_bytes(),
+            );
+        }
+    }
+}
+
+/// The passes of a vector export, simplified within `tolerance` and optimized if enabled, noting
+/// the pen-up travel in `report`
+fn plotted_passes(
+    drawing: &Drawing,
+    settings: &ExportSettings,
+    tolerance: f32,
+    report: &mut ExportReport,
+) -> Vec<Pass> {
+    let mut passes = drawing.passes();
+    for path in passes.iter_mut().flat_map(|pass| &mut pass.paths) {
+        path.points = simplify(&path.points, tolerance);
+    }
+
+    // Plotters start from the corner of the page nearest the bottom left of the drawing
+    let bounds = drawing.bounds();
+    let before = optimize::travel(&passes, bounds.min);
+    if settings.optimize {
+        optimize::optimize(&mut passes, bounds.min);
+    }
+    let after = optimize::travel(&passes, bounds.min);
+
+    let mm_per_unit = settings.page.placement(bounds).matrix2.x_axis.length();
+    report.travel = Some((mm_per_unit * before, mm_per_unit * after));
+
+    passes
+}
+
+/// A number with no more precision than is visible
+fn number(n: f32) -> String {
+    let n = format!("{n:.2}");
+    let n = n.trim_end_matches('0').trim_end_matches('.');
+    if n == "-0" {
+        "0".to_string()
+    } else {
+        n.to_string()
+    }
+}
@@ -0,0 +1,100 @@
+use super::{number, Drawing, GcodeSettings, Page, Pass};
+use crate::prelude::*;
+use std::fmt::Write;
+
+/// Write the drawing as G-code in millimeters, plotting each color in its own pass
+pub fn write(drawing: &Drawing, passes: &[Pass], page: &Page, settings: &GcodeSettings) -> String {
+    let placement = page.placement(drawing.bounds());
+    let position = |p: Vec2| {
+        let p = placement.transform_point2(p);
+        format!("X{} Y{}", number(p.x), number(p.y))
+    };
+
+    let mut gcode = String::new();
+
+    let _ = writeln!(gcode, "G21 ; millimeters");
+    let _ = writeln!(gcode, "G90 ; absolute positions");
+    let _ = writeln!(gcode, "{}", settings.pen_up);
+
+    for (i, pass) in passes.iter().enumerate() {
+        let _ = writeln!(gcode, "; pass {}: pen {}", i + 1, pass.color.to_hex());
+        let _ = writeln!(gcode, "{}", settings.tool_change);
+
+        for path in &pass.paths {
+            let Some((first, rest)) = path.points.split_first() else {
+                continue;
+            };
+
+            let _ = writeln!(gcode, "G0 {}", position(*first));
+            let _ = writeln!(gcode, "{}", settings.pen_down);
+            for (j, &p) in rest.iter().enumerate() {
+                // The feed rate is modal, but the pen commands may have changed it
+                if j == 0 {
+                    let _ = writeln!(gcode, "G1 {} F{}", position(p), number(settings.feed_rate));
+                } else {
+                    let _ = writeln!(gcode, "G1 {}", position(p));
+                }
+            }
+            let _ = writeln!(gcode, "{}", settings.pen_up);
+        }
+    }
+
+    let _ = writeln!(gcode, "G0 X0 Y0");
+    let _ = writeln!(gcode, "M2");
+
+    gcode
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        export::DrawnLine,
+        spiro::{LineColor, PenStyle},
+    };
+
+    #[test]
+    fn write_plots_each_path_with_the_pen_down() {
+        let drawing = Drawing {
+            background: Srgba::WHITE,
+            lines: vec![DrawnLine {
+                points: vec![Vec2::new(-10.0, 0.0), Vec2::ZERO, Vec2::new(10.0, 0.0)],
+                color: LineColor(Srgba::RED),
+                width: 0.0,
+                style: PenStyle::default(),
+            }],
+            gears: Vec::new(),
+        };
+        let page = Page {
+            size: Vec2::new(200.0, 100.0),
+            margin: 0.0,
+            scale: Some(1.0),
+        };
+
+        let gcode = write(
+            &drawing,
+            &drawing.passes(),
+            &page,
+            &GcodeSettings::default(),
+        );
+        let lines: Vec<_> = gcode.lines().collect();
+
+        assert_eq!(
+            lines[..3],
+            ["G21 ; millimeters", "G90 ; absolute positions", "G0 Z5"]
+        );
+        assert_eq!(
+            lines[4..],
+            [
+                "M0",
+                "G0 X90 Y50",
+                "G1 Z0",
+                "G1 X100 Y50 F3000",
+                "G1 X110 Y50",
+                "G0 Z5",
+                "G0 X0 Y0",
+                "M2",
+            ]
+        );
+    }
+}
@@ -0,0 +1,99 @@
+use super::{Drawing, HpglSettings, Page, Pass};
+use crate::prelude::*;
+use bevy::math::Affine2;
+use std::fmt::Write;
+
+/// Plotter units per millimeter
+const UNITS_PER_MM: f32 = 40.0;
+
+/// Write the drawing as HPGL, selecting a pen for each color
+pub fn write(drawing: &Drawing, passes: &[Pass], page: &Page, settings: &HpglSettings) -> String {
+    let placement = Affine2::from_scale(Vec2::splat(UNITS_PER_MM))
+        * Affine2::from_angle(-settings.rotation.to_radians())
+        * Affine2::from_translation(-settings.origin)
+        * page.placement(drawing.bounds());
+    let position = |p: Vec2| {
+        let p = placement.transform_point2(p).round();
+        format!("{},{}", p.x as i32, p.y as i32)
+    };
+
+    let mut hpgl = String::new();
+
+    let _ = write!(hpgl, "IN;");
+
+    for (i, pass) in passes.iter().enumerate() {
+        let pen = i as u32 % settings.pens.max(1) + 1;
+        let _ = write!(hpgl, "\nSP{pen};");
+
+        for path in &pass.paths {
+            let Some((first, rest)) = path.points.split_first() else {
+                continue;
+            };
+
+            // A lone point is still marked by putting the pen down on it
+            let rest = if rest.is_empty() {
+                std::slice::from_ref(first)
+            } else {
+                rest
+            };
+            let points: Vec<_> = rest.iter().map(|&p| position(p)).collect();
+
+            let _ = write!(hpgl, "\nPU{};PD{};", position(*first), points.join(","));
+        }
+    }
+
+    let _ = writeln!(hpgl, "\nPU;SP0;");
+
+    hpgl
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        export::DrawnLine,
+        spiro::{LineColor, PenStyle},
+    };
+
+    fn line(points: &[Vec2], color: Srgba) -> DrawnLine {
+        DrawnLine {
+            points: points.to_vec(),
+            color: LineColor(color),
+            width: 0.0,
+            style: PenStyle::default(),
+        }
+    }
+
+    #[test]
+    fn write_reuses_pens_for_further_colors() {
+        let drawing = Drawing {
+            background: Srgba::WHITE,
+            lines: vec![
+                line(&[Vec2::new(-10.0, 0.0), Vec2::new(10.0, 0.0)], Srgba::RED),
+                line(&[Vec2::new(0.0, -5.0)], Srgba::GREEN),
+                line(&[Vec2::new(0.0, 5.0), Vec2::ZERO], Srgba::BLUE),
+            ],
+            gears: Vec::new(),
+        };
+        let page = Page {
+            size: Vec2::new(200.0, 100.0),
+            margin: 0.0,
+            scale: Some(1.0),
+        };
+        let settings = HpglSettings {
+            pens: 2,
+            ..default()
+        };
+
+        let hpgl = write(&drawing, &drawing.passes(), &page, &settings);
+
+        assert_eq!(
+            hpgl,
+            "IN;\
+             \nSP1;\nPU3600,2000;PD4400,2000;\
+             \nSP2;\nPU4000,1800;PD4000,1800;\
+             \nSP1;\nPU4000,2200;PD4000,2000;\
+             \nPU;SP0;\n"
+        );
+    }
+}
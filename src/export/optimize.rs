@@ -0,0 +1,275 @@
+use super::{Pass, Polyline};
+use crate::prelude::*;
+use std::collections::HashMap;
+
+/// Endpoints closer than this are considered touching
+const TOUCHING: f32 = 1e-3;
+
+/// Shorten the distance the pen travels lifted, starting from `start`.
+///
+/// Within each pass, polylines are drawn nearest first, reversed when their end is nearer than
+/// their start, and merged with the polyline before them when they pick up where it stopped.
+pub fn optimize(passes: &mut [Pass], start: Vec2) {
+    let mut position = start;
+
+    for pass in passes {
+        let mut ends = Ends::new(&pass.paths);
+        let mut remaining: Vec<_> = std::mem::take(&mut pass.paths)
+            .into_iter()
+            .map(Some)
+            .collect();
+        let mut ordered: Vec<Polyline> = Vec::with_capacity(remaining.len());
+
+        while let Some((index, reversed)) = ends.nearest(position) {
+            let Some(mut path) = remaining[index].take() else {
+                break;
+            };
+            ends.remove(index, &path);
+            if reversed {
+                path.points.reverse();
+            }
+
+            let (Some(&first), Some(&last)) = (path.points.first(), path.points.last()) else {
+                continue;
+            };
+            position = last;
+
+            match ordered.last_mut() {
+                Some(previous)
+                    if previous.width == path.width
+                        && previous.style == path.style
+                        && previous
+                            .points
+                            .last()
+                            .is_some_and(|&end| end.distance(first) < TOUCHING) =>
+                {
+                    previous.points.extend(&path.points[1..]);
+                }
+                _ => ordered.push(path),
+            }
+        }
+
+        pass.paths = ordered;
+    }
+}
+
+/// Total distance the pen travels lifted, starting from `start`
+pub fn travel(passes: &[Pass], start: Vec2) -> f32 {
+    let mut position = start;
+    let mut travel = 0.0;
+
+    for path in passes.iter().flat_map(|pass| &pass.paths) {
+        let (Some(&first), Some(&last)) = (path.points.first(), path.points.last()) else {
+            continue;
+        };
+        travel += position.distance(first);
+        position = last;
+    }
+
+    travel
+}
+
+/// Ends of the paths left to draw, bucketed into a grid so that finding the nearest one only looks
+/// at the paths around it
+struct Ends {
+    /// Width of a cell
+    size: f32,
+    /// Index of the path each end belongs to, whether it is its last point, and the end itself
+    cells: HashMap<IVec2, Vec<(usize, bool, Vec2)>>,
+    /// Bounds of the cells that held an end
+    min: IVec2,
+    max: IVec2,
+}
+
+impl Ends {
+    fn new(paths: &[Polyline]) -> Self {
+        let ends: Vec<_> = paths
+            .iter()
+            .enumerate()
+            .filter_map(|(i, path)| {
+                let (&first, &last) = (path.points.first()?, path.points.last()?);
+                Some([(i, false, first), (i, true, last)])
+            })
+            .flatten()
+            .collect();
+
+        // Aim for about one end per cell, even when the ends line up along one axis
+        let (lo, hi) = ends.iter().fold(
+            (Vec2::INFINITY, Vec2::NEG_INFINITY),
+            |(lo, hi), &(.., end)| (lo.min(end), hi.max(end)),
+        );
+        let extent = hi - lo;
+        let count = ends.len() as f32;
+        let size = (extent.x * extent.y / count)
+            .sqrt()
+            .max(extent.max_element() / count);
+        let size = if size.is_finite() && size > TOUCHING {
+            size
+        } else {
+            1.0
+        };
+
+        let mut grid = Self {
+            size,
+            cells: HashMap::new(),
+            min: IVec2::MAX,
+            max: IVec2::MIN,
+        };
+        for (i, reversed, end) in ends {
+            let cell = grid.cell(end);
+            grid.min = grid.min.min(cell);
+            grid.max = grid.max.max(cell);
+            grid.cells.entry(cell).or_default().push((i, reversed, end));
+        }
+        grid
+    }
+
+    fn cell(&self, point: Vec2) -> IVec2 {
+        (point / self.size).floor().as_ivec2()
+    }
+
+    /// Forget the ends of the path at `index`
+    fn remove(&mut self, index: usize, path: &Polyline) {
+        for &end in path.points.first().into_iter().chain(path.points.last()) {
+            let cell = self.cell(end);
+            if let Some(entries) = self.cells.get_mut(&cell) {
+                entries.retain(|&(i, ..)| i != index);
+                if entries.is_empty() {
+                    self.cells.remove(&cell);
+                }
+            }
+        }
+    }
+
+    /// Index of the path with an end nearest `position`, and whether that is its last point,
+    /// searching rings of cells outwards until no nearer end can be left
+    fn nearest(&self, position: Vec2) -> Option<(usize, bool)> {
+        if self.cells.is_empty() {
+            return None;
+        }
+
+        let center = self.cell(position);
+        let rings = (center - self.min)
+            .abs()
+            .max((self.max - center).abs())
+            .max_element();
+
+        let mut nearest = None;
+        let mut nearest_distance = f32::INFINITY;
+        for ring in 0..=rings {
+            // Ends in this ring and beyond are at least this far from `position`
+            let reach = (ring - 1).max(0) as f32 * self.size;
+            if nearest_distance <= reach * reach {
+                break;
+            }
+
+            for cell in ring_cells(center, ring) {
+                for &(i, reversed, end) in self.cells.get(&cell).into_iter().flatten() {
+                    let distance = position.distance_squared(end);
+                    if distance < nearest_distance {
+                        nearest = Some((i, reversed));
+                        nearest_distance = distance;
+                    }
+                }
+            }
+        }
+
+        nearest
+    }
+}
+
+/// Cells `ring` steps away from `center` along either axis, going around the square they make
+fn ring_cells(center: IVec2, ring: i32) -> impl Iterator<Item = IVec2> {
+    let around = (-ring..ring).flat_map(move |i| {
+        [
+            IVec2::new(i, -ring),
+            IVec2::new(ring, i),
+            IVec2::new(-i, ring),
+            IVec2::new(-ring, -i),
+        ]
+    });
+    std::iter::once(IVec2::ZERO)
+        .filter(move |_| ring == 0)
+        .chain(around)
+        .map(move |offset| center + offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spiro::PenStyle;
+
+    fn pass(paths: &[&[Vec2]]) -> Pass {
+        Pass {
+            color: Srgba::BLACK,
+            paths: paths
+                .iter()
+                .map(|points| Polyline {
+                    points: points.to_vec(),
+                    width: 1.0,
+                    style: PenStyle::default(),
+                })
+                .collect(),
+        }
+    }
+
+    fn points(passes: &[Pass]) -> Vec<Vec<Vec2>> {
+        passes
+            .iter()
+            .flat_map(|pass| &pass.paths)
+            .map(|path| path.points.clone())
+            .collect()
+    }
+
+    #[test]
+    fn optimize_draws_nearest_first_and_reverses_paths() {
+        let far = [Vec2::new(10.0, 0.0), Vec2::new(11.0, 0.0)];
+        let backwards = [Vec2::new(3.0, 0.0), Vec2::new(1.0, 0.0)];
+        let middle = [Vec2::new(5.0, 0.0), Vec2::new(6.0, 0.0)];
+        let mut passes = [pass(&[&far, &backwards, &middle])];
+        assert_eq!(travel(&passes, Vec2::ZERO), 22.0);
+
+        optimize(&mut passes, Vec2::ZERO);
+
+        assert_eq!(
+            points(&passes),
+            [
+                vec![Vec2::new(1.0, 0.0), Vec2::new(3.0, 0.0)],
+                middle.to_vec(),
+                far.to_vec(),
+            ]
+        );
+        assert_eq!(travel(&passes, Vec2::ZERO), 7.0);
+    }
+
+    #[test]
+    fn optimize_merges_paths_that_touch() {
+        let first = [Vec2::ZERO, Vec2::ONE];
+        let second = [Vec2::ONE, Vec2::new(2.0, 0.0)];
+        let mut passes = [pass(&[&second, &first])];
+
+        optimize(&mut passes, Vec2::ZERO);
+
+        assert_eq!(
+            points(&passes),
+            [vec![Vec2::ZERO, Vec2::ONE, Vec2::new(2.0, 0.0)]]
+        );
+        assert_eq!(travel(&passes, Vec2::ZERO), 0.0);
+    }
+
+    #[test]
+    fn optimize_keeps_passes_apart() {
+        let a = [Vec2::new(5.0, 0.0), Vec2::new(6.0, 0.0)];
+        let b = [Vec2::new(1.0, 0.0), Vec2::new(2.0, 0.0)];
+        let mut passes = [pass(&[&a]), pass(&[&b])];
+
+        optimize(&mut passes, Vec2::ZERO);
+
+        // Each pass is plotted with its own pen, so the nearer path still waits for its pass and is
+        // then started from where the pass before it stopped
+        assert_eq!(
+            points(&passes),
+            [a.to_vec(), vec![Vec2::new(2.0, 0.0), Vec2::new(1.0, 0.0)]]
+        );
+    }
+}
@@ -0,0 +1,130 @@
+use super::{Drawing, ExportSettings, MARGIN};
+use crate::{
+    prelude::*,
+    stroke::{Cap, Join},
+};
+use std::error::Error;
+use tiny_skia::{
+    Color, LineCap, LineJoin, Paint, Path, PathBuilder, Pixmap, Stroke, Transform as PixelTransform,
+};
+
+/// Rasterize the drawing to an antialiased PNG image `settings.png_width` pixels wide
+pub fn write(drawing: &Drawing, settings: &ExportSettings) -> Result<Vec<u8>, Box<dyn Error>> {
+    let bounds = drawing.bounds().inflate(MARGIN);
+    let size = bounds.size();
+
+    let scale = settings.png_width as f32 / size.x;
+    let height = (scale * size.y).round().max(1.0) as u32;
+    let mut pixmap = Pixmap::new(settings.png_width, height).ok_or("image too large")?;
+
+    if !settings.transparent {
+        pixmap.fill(color(drawing.background));
+    }
+
+    // Pixel rows go down, flip the world around the x axis
+    let transform = PixelTransform::from_row(
+        scale,
+        0.0,
+        0.0,
+        -scale,
+        -scale * bounds.min.x,
+        scale * bounds.max.y,
+    );
+
+    for gear in &drawing.gears {
+        let Some(path) = path(&gear.outline, true) else {
+            continue;
+        };
+
+        pixmap.stroke_path(
+            &path,
+            &paint(gear.color),
+            &Stroke::default(),
+            transform,
+            None,
+        );
+    }
+
+    for line in &drawing.lines {
+        let stroke = Stroke {
+            width: line.width,
+            line_cap: match line.style.cap {
+                Cap::Butt => LineCap::Butt,
+                Cap::Square => LineCap::Square,
+                Cap::Round => LineCap::Round,
+            },
+            line_join: match line.style.join {
+                Join::Miter => LineJoin::Miter,
+                Join::Bevel => LineJoin::Bevel,
+                Join::Round => LineJoin::Round,
+            },
+            ..default()
+        };
+
+        for (color, run) in line.runs() {
+            let Some(path) = path(run, false) else {
+                continue;
+            };
+
+            pixmap.stroke_path(&path, &paint(color), &stroke, transform, None);
+        }
+    }
+
+    encode(&pixmap, settings.dpi)
+}
+
+/// Encode the pixels as a PNG image, tagged to print at `dpi`
+fn encode(pixmap: &Pixmap, dpi: u32) -> Result<Vec<u8>, Box<dyn Error>> {
+    let data: Vec<u8> = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let pixel = pixel.demultiply();
+            [pixel.red(), pixel.green(), pixel.blue(), pixel.alpha()]
+        })
+        .collect();
+
+    let mut bytes = Vec::new();
+    let mut encoder = ::png::Encoder::new(&mut bytes, pixmap.width(), pixmap.height());
+    encoder.set_color(::png::ColorType::Rgba);
+    encoder.set_depth(::png::BitDepth::Eight);
+
+    let pixels_per_meter = (dpi as f32 / 0.0254).round() as u32;
+    encoder.set_pixel_dims(Some(::png::PixelDimensions {
+        xppu: pixels_per_meter,
+        yppu: pixels_per_meter,
+        unit: ::png::Unit::Meter,
+    }));
+
+    encoder.write_header()?.write_image_data(&data)?;
+
+    Ok(bytes)
+}
+
+/// Path through `points` in world coordinates
+fn path(points: &[Vec2], closed: bool) -> Option<Path> {
+    let (first, rest) = points.split_first()?;
+
+    let mut path = PathBuilder::new();
+    path.move_to(first.x, first.y);
+    // A lone point still gets its caps from a segment of no length
+    for p in if rest.is_empty() { points } else { rest } {
+        path.line_to(p.x, p.y);
+    }
+    if closed {
+        path.close();
+    }
+    path.finish()
+}
+
+fn color(color: Srgba) -> Color {
+    let [r, g, b, a] = color.to_u8_array();
+    Color::from_rgba8(r, g, b, a)
+}
+
+fn paint(color: Srgba) -> Paint<'static> {
+    let mut paint = Paint::default();
+    paint.set_color(self::color(color));
+    paint.anti_alias = true;
+    paint
+}
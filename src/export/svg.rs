@@ -0,0 +1,82 @@
+use super::{number, Drawing, Pass, MARGIN};
+use crate::{
+    prelude::*,
+    stroke::{Cap, Join},
+};
+use std::fmt::Write;
+
+/// Write the drawing as an SVG document, one world unit to a pixel
+pub fn write(drawing: &Drawing, passes: &[Pass]) -> String {
+    let bounds = drawing.bounds().inflate(MARGIN);
+    let size = bounds.size();
+
+    let mut svg = String::new();
+
+    // SVG's y axis points down, flip the world around the x axis
+    let _ = writeln!(
+        svg,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="{x} {y} {w} {h}">"#,
+        x = number(bounds.min.x),
+        y = number(-bounds.max.y),
+        w = number(size.x),
+        h = number(size.y),
+    );
+
+    let _ = writeln!(
+        svg,
+        r#"<rect x="{x}" y="{y}" width="{w}" height="{h}" {fill}/>"#,
+        x = number(bounds.min.x),
+        y = number(-bounds.max.y),
+        w = number(size.x),
+        h = number(size.y),
+        fill = paint("fill", drawing.background),
+    );
+
+    for pass in passes {
+        let _ = writeln!(svg, r#"<g fill="none" {}>"#, paint("stroke", pass.color));
+
+        for path in &pass.paths {
+            let cap = match path.style.cap {
+                Cap::Butt => "butt",
+                Cap::Square => "square",
+                Cap::Round => "round",
+            };
+            let join = match path.style.join {
+                Join::Miter => "miter",
+                Join::Bevel => "bevel",
+                Join::Round => "round",
+            };
+            let _ = writeln!(
+                svg,
+                r#"<polyline points="{}" stroke-width="{}" stroke-linecap="{cap}" stroke-linejoin="{join}"/>"#,
+                points(&path.points),
+                number(path.width),
+            );
+        }
+
+        let _ = writeln!(svg, "</g>");
+    }
+
+    let _ = writeln!(svg, "</svg>");
+
+    svg
+}
+
+/// `attribute` set to `color`, with a separate opacity for translucent colors
+fn paint(attribute: &str, color: Srgba) -> String {
+    let [r, g, b, _] = color.to_u8_array();
+    let mut paint = format!(r##"{attribute}="#{r:02x}{g:02x}{b:02x}""##);
+    if color.alpha < 1.0 {
+        let _ = write!(paint, r#" {attribute}-opacity="{}""#, number(color.alpha));
+    }
+    paint
+}
+
+/// Points in document coordinates
+fn points(points: &[Vec2]) -> String {
+    points
+        .iter()
+        .map(|p| format!("{},{}", number(p.x), number(-p.y)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
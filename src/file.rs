@@ -0,0 +1,95 @@
+use crate::prelude::*;
+use bevy::tasks::IoTaskPool;
+use std::sync::mpsc::Sender;
+
+/// Hand `bytes` to the user as a file, through a save dialog on native and a download on the web
+pub fn save(file_name: String, bytes: Vec<u8>) {
+    #[cfg(not(target_arch = "wasm32"))]
+    IoTaskPool::get()
+        .spawn(async move {
+            let handle = rfd::AsyncFileDialog::new()
+                .set_file_name(&file_name)
+                .save_file()
+                .await;
+
+            if let Some(handle) = handle {
+                if let Err(err) = handle.write(&bytes).await {
+                    error!("Failed to save {}: {err}", handle.file_name());
+                }
+            }
+        })
+        .detach();
+
+    #[cfg(target_arch = "wasm32")]
+    if let Err(err) = download(&file_name, &bytes) {
+        error!("Failed to download {file_name}: {err:?}");
+    }
+}
+
+/// Let the user pick a file with one of `extensions`, its contents are sent to `sender` once read.
+///
+/// The browser's upload dialog stands in for the file dialog on the web.
+pub fn open(extensions: &'static [&'static str], sender: Sender<Vec<u8>>) {
+    IoTaskPool::get()
+        .spawn(async move {
+            let handle = rfd::AsyncFileDialog::new()
+                .add_filter("Supported files", extensions)
+                .pick_file()
+                .await;
+
+            if let Some(handle) = handle {
+                let _ = sender.send(handle.read().await);
+            }
+        })
+        .detach();
+}
+
+/// Keep `text` under `name` across runs, in a file in the working directory on native and in the
+/// browser's local storage on the web
+pub fn store(name: &str, text: &str) {
+    #[cfg(not(target_arch = "wasm32"))]
+    if let Err(err) = std::fs::write(name, text) {
+        error!("Failed to store {name}: {err}");
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    if let Err(err) = local_storage().and_then(|storage| storage.set_item(name, text)) {
+        error!("Failed to store {name}: {err:?}");
+    }
+}
+
+/// Text last stored under `name`, if any
+pub fn load(name: &str) -> Option<String> {
+    #[cfg(not(target_arch = "wasm32"))]
+    return std::fs::read_to_string(name).ok();
+
+    #[cfg(target_arch = "wasm32")]
+    return local_storage().ok()?.get_item(name).ok()?;
+}
+
+#[cfg(target_arch = "wasm32")]
+fn local_storage() -> Result<web_sys::Storage, wasm_bindgen::JsValue> {
+    web_sys::window()
+        .ok_or("no window")?
+        .local_storage()?
+        .ok_or_else(|| "no local storage".into())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn download(file_name: &str, bytes: &[u8]) -> Result<(), wasm_bindgen::JsValue> {
+    use wasm_bindgen::JsCast;
+
+    let parts = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes));
+    let blob = web_sys::Blob::new_with_u8_array_sequence(&parts)?;
+    let url = web_sys::Url::create_object_url_with_blob(&blob)?;
+
+    let document = web_sys::window()
+        .and_then(|window| window.document())
+        .ok_or("no document")?;
+    let anchor: web_sys::HtmlAnchorElement = document.create_element("a")?.dyn_into()?;
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+
+    web_sys::Url::revoke_object_url(&url)
+}
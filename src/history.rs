@@ -0,0 +1,596 @@
+use crate::{
+    prelude::*,
+    scene::{FixedGear, RotatingGear, SavedPen, SceneGears},
+    spiro::{Fixed, Pen, Rotation},
+};
+use bevy::ecs::system::EntityCommands;
+use bevy_egui::EguiContexts;
+use std::collections::HashMap;
+
+/// How long the gears must stay unchanged before their edits become a step, so that dragging a
+/// value or a gear around ends up as a single step
+const SETTLE_SECONDS: f32 = 0.25;
+
+/// Steps kept before the oldest are forgotten
+const MAX_STEPS: usize = 128;
+
+pub(super) fn plugin(app: &mut App) {
+    app //
+        .insert_resource(History {
+            // The gears spawned on startup are where history begins
+            resync: true,
+            ..default()
+        })
+        .add_systems(Update, (shortcuts, pointer_held.pipe(record_edits)))
+        .observe(undo)
+        .observe(redo)
+        .observe(remove);
+}
+
+/// Revert the most recent step
+#[derive(Event)]
+pub struct Undo;
+
+/// Repeat the most recently reverted step
+#[derive(Event)]
+pub struct Redo;
+
+/// Despawn a gear along with its children, or a pen, in a way that can be undone
+#[derive(Event)]
+pub struct Remove(pub Entity);
+
+/// Steps that can be undone and redone, each made up of the edits to the gears between two moments
+/// they settled down
+#[derive(Resource, Default)]
+pub struct History {
+    undo: Vec<Vec<Edit>>,
+    redo: Vec<Vec<Edit>>,
+    /// Parameters of every gear as of the last step
+    recorded: HashMap<Entity, Params>,
+    /// Parameters of every gear as of the last change, and when that was
+    latest: HashMap<Entity, Params>,
+    changed_at: f32,
+    /// Take the gears as they are once they settle, after being changed by undo or redo
+    resync: bool,
+}
+
+impl History {
+    pub fn can_undo(&self) -> bool {
+        !self.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo.is_empty()
+    }
+
+    fn push(&mut self, step: Vec<Edit>) {
+        if self.undo.len() == MAX_STEPS {
+            self.undo.remove(0);
+        }
+        self.undo.push(step);
+        self.redo.clear();
+    }
+
+    /// Undo or redo `step`, returning it ready to go the other way
+    fn apply(
+        &mut self,
+        commands: &mut Commands,
+        scene: &SceneGears,
+        q_parents: &Query<&Parent>,
+        mut step: Vec<Edit>,
+        undo: bool,
+    ) -> Vec<Edit> {
+        if undo {
+            step.reverse();
+        }
+
+        for i in 0..step.len() {
+            let mut respawned = Vec::new();
+
+            match &mut step[i] {
+                Edit::Change {
+                    entity,
+                    before,
+                    after,
+                } => {
+                    let params = if undo { before } else { after };
+                    if let Some(mut entity) = commands.get_entity(*entity) {
+                        params.apply(&mut entity);
+                    }
+                }
+                Edit::Presence {
+                    entity,
+                    spawned,
+                    saved,
+                } => {
+                    if *spawned != undo {
+                        if let Some((new, children)) =
+                            saved.take().and_then(|saved| saved.spawn(commands))
+                        {
+                            respawned.push((*entity, new));
+                            respawned.extend(children);
+                        }
+                    } else {
+                        *saved = Saved::take(scene, q_parents, *entity);
+                        if let Some(entity) = commands.get_entity(*entity) {
+                            entity.despawn_recursive();
+                        }
+                    }
+                }
+            }
+
+            // Point everything at the entities that replaced the respawned ones
+            for (old, new) in respawned {
+                let edits = self
+                    .undo
+                    .iter_mut()
+                    .chain(&mut self.redo)
+                    .flatten()
+                    .chain(&mut step);
+                for edit in edits {
+                    edit.remap(old, new);
+                }
+            }
+        }
+
+        if undo {
+            step.reverse();
+        }
+
+        self.resync = true;
+        step
+    }
+}
+
+/// A change to the gears, with what it takes to revert it
+enum Edit {
+    /// The parameters of a gear changed
+    Change {
+        entity: Entity,
+        before: Params,
+        after: Params,
+    },
+    /// A gear was spawned or despawned with its children, `saved` holds it while it's gone
+    Presence {
+        entity: Entity,
+        spawned: bool,
+        saved: Option<Saved>,
+    },
+}
+
+impl Edit {
+    fn remap(&mut self, old: Entity, new: Entity) {
+        let replace = |entity: &mut Entity| {
+            if *entity == old {
+                *entity = new;
+            }
+        };
+
+        match self {
+            Edit::Change { entity, .. } => replace(entity),
+            Edit::Presence { entity, saved, .. } => {
+                replace(entity);
+                match saved {
+                    Some(Saved::Fixed { children, .. }) => children.iter_mut().for_each(replace),
+                    Some(Saved::Rotating {
+                        parent, children, ..
+                    }) => {
+                        replace(parent);
+                        children.iter_mut().for_each(replace);
+                    }
+                    Some(Saved::Pen { parent, .. }) => replace(parent),
+                    None => {}
+                }
+            }
+        }
+    }
+}
+
+/// Parameters of a gear or pen that can be edited, leaving out its line and anything that changes
+/// on its own while the gears turn
+#[derive(Clone, PartialEq)]
+enum Params {
+    Fixed(FixedGear),
+    Rotating(RotatingGear),
+    Pen(SavedPen),
+}
+
+impl Params {
+    fn apply(&self, entity: &mut EntityCommands) {
+        match self {
+            Params::Fixed(gear) => gear.apply(entity),
+            Params::Rotating(gear) => gear.apply(entity),
+            Params::Pen(pen) => pen.apply(entity),
+        }
+    }
+}
+
+/// A despawned gear complete with its children, or a despawned pen with its line
+enum Saved {
+    Fixed {
+        gear: FixedGear,
+        /// Entities of the rotating descendants when they were despawned
+        children: Vec<Entity>,
+    },
+    Rotating {
+        parent: Entity,
+        gear: RotatingGear,
+        /// Entities of its pens and of the gears rolling on it, and on those, when they were
+        /// despawned
+        children: Vec<Entity>,
+    },
+    Pen {
+        parent: Entity,
+        pen: SavedPen,
+        /// Rotation of the gear carrying it when it was despawned, to carry on drawing from
+        rotation: f32,
+    },
+}
+
+impl Saved {
+    /// Copy the gear or pen `entity` before despawning it
+    fn take(scene: &SceneGears, q_parents: &Query<&Parent>, entity: Entity) -> Option<Self> {
+        if let Some((gear, children)) = scene.fixed_gear(entity, true) {
+            return Some(Saved::Fixed { gear, children });
+        }
+
+        let parent = q_parents.get(entity).ok()?.get();
+        if let Some((gear, children)) = scene.rotating_gear(entity, true) {
+            return Some(Saved::Rotating {
+                parent,
+                gear,
+                children,
+            });
+        }
+
+        let pen = scene.pen(entity, true)?;
+        let rotation = scene.rotation(parent)?;
+        Some(Saved::Pen {
+            parent,
+            pen,
+            rotation,
+        })
+    }
+
+    /// Entities of the descendants when it was despawned
+    fn children(&self) -> &[Entity] {
+        match self {
+            Saved::Fixed { children, .. } | Saved::Rotating { children, .. } => children,
+            Saved::Pen { .. } => &[],
+        }
+    }
+
+    /// Spawn the gear again, returning its new entity along with the entities its descendants used
+    /// to have paired with those replacing them
+    fn spawn(self, commands: &mut Commands) -> Option<(Entity, Vec<(Entity, Entity)>)> {
+        match self {
+            Saved::Fixed { gear, children } => {
+                let (entity, new_children) = gear.spawn(commands);
+                Some((entity, children.into_iter().zip(new_children).collect()))
+            }
+            Saved::Rotating {
+                parent,
+                gear,
+                children,
+            } => {
+                let mut entities = Vec::new();
+                commands
+                    .get_entity(parent)?
+                    .with_children(|parent| entities = gear.spawn(parent));
+                let (&entity, new_children) = entities.split_first()?;
+                Some((
+                    entity,
+                    children.into_iter().zip(new_children.to_vec()).collect(),
+                ))
+            }
+            Saved::Pen {
+                parent,
+                pen,
+                rotation,
+            } => {
+                let mut entity = None;
+                commands
+                    .get_entity(parent)?
+                    .with_children(|parent| entity = Some(pen.spawn(parent, rotation)));
+                Some((entity?, Vec::new()))
+            }
+        }
+    }
+}
+
+/// Current parameters of every gear and pen
+fn params(
+    scene: &SceneGears,
+    q_fixed: &Query<Entity, With<Fixed>>,
+    q_rotating: &Query<Entity, With<Rotation>>,
+    q_pens: &Query<Entity, With<Pen>>,
+) -> HashMap<Entity, Params> {
+    let fixed = q_fixed.iter().filter_map(|entity| {
+        let (mut gear, _children) = scene.fixed_gear(entity, false)?;
+        gear.rotating.clear();
+        Some((entity, Params::Fixed(gear)))
+    });
+    let rotating = q_rotating.iter().filter_map(|entity| {
+        let (mut gear, _children) = scene.rotating_gear(entity, false)?;
+        gear.rotation = 0.0;
+        gear.paused = false;
+        gear.pens.clear();
+        gear.rotating.clear();
+        Some((entity, Params::Rotating(gear)))
+    });
+    let pens = q_pens
+        .iter()
+        .filter_map(|entity| Some((entity, Params::Pen(scene.pen(entity, false)?))));
+
+    fixed.chain(rotating).chain(pens).collect()
+}
+
+/// Whether a mouse button is down or the sidebar is being dragged, edits keep going until it is let
+/// go
+fn pointer_held(mouse: Res<ButtonInput<MouseButton>>, mut contexts: EguiContexts) -> bool {
+    mouse.get_pressed().next().is_some() || contexts.ctx_mut().is_using_pointer()
+}
+
+/// Turn edits into steps once the gears settle down and the pointer is let go
+#[allow(clippy::too_many_arguments)]
+fn record_edits(
+    In(held): In<bool>,
+    mut history: ResMut<History>,
+    scene: SceneGears,
+    q_fixed: Query<Entity, With<Fixed>>,
+    q_rotating: Query<Entity, With<Rotation>>,
+    q_pens: Query<Entity, With<Pen>>,
+    q_parents: Query<&Parent>,
+    time: Res<Time>,
+) {
+    let current = params(&scene, &q_fixed, &q_rotating, &q_pens);
+
+    if current != history.latest {
+        history.latest = current;
+        history.changed_at = time.elapsed_seconds();
+        return;
+    }
+
+    if held || time.elapsed_seconds() - history.changed_at < SETTLE_SECONDS {
+        return;
+    }
+
+    if history.resync {
+        history.recorded = current;
+        history.resync = false;
+        return;
+    }
+
+    if current == history.recorded {
+        return;
+    }
+
+    // Gears that vanished without a trace, like when opening a scene, can't be brought back and
+    // neither can the steps involving them
+    if history
+        .recorded
+        .keys()
+        .any(|entity| !current.contains_key(entity))
+    {
+        history.undo.clear();
+        history.redo.clear();
+        history.recorded = current;
+        return;
+    }
+
+    let mut step = Vec::new();
+    for (&entity, after) in &current {
+        match history.recorded.get(&entity) {
+            Some(before) if before != after => step.push(Edit::Change {
+                entity,
+                before: before.clone(),
+                after: after.clone(),
+            }),
+            Some(_) => {}
+            None => {
+                // Pens and gears spawned with the gear they belong to come and go with it
+                let new_parent = q_parents
+                    .get(entity)
+                    .is_ok_and(|parent| !history.recorded.contains_key(&parent.get()));
+                if !new_parent {
+                    step.push(Edit::Presence {
+                        entity,
+                        spawned: true,
+                        saved: None,
+                    });
+                }
+            }
+        }
+    }
+
+    history.push(step);
+    history.recorded = current;
+}
+
+fn undo(
+    _trigger: Trigger<Undo>,
+    mut commands: Commands,
+    mut history: ResMut<History>,
+    scene: SceneGears,
+    q_parents: Query<&Parent>,
+) {
+    let step = rq!(history.undo.pop());
+    let step = history.apply(&mut commands, &scene, &q_parents, step, true);
+    history.redo.push(step);
+}
+
+fn redo(
+    _trigger: Trigger<Redo>,
+    mut commands: Commands,
+    mut history: ResMut<History>,
+    scene: SceneGears,
+    q_parents: Query<&Parent>,
+) {
+    let step = rq!(history.redo.pop());
+    let step = history.apply(&mut commands, &scene, &q_parents, step, false);
+    history.undo.push(step);
+}
+
+fn remove(
+    trigger: Trigger<Remove>,
+    mut commands: Commands,
+    mut history: ResMut<History>,
+    scene: SceneGears,
+    q_parents: Query<&Parent>,
+) {
+    let entity = trigger.event().0;
+    let saved = rq!(Saved::take(&scene, &q_parents, entity));
+
+    for child in saved.children() {
+        history.recorded.remove(child);
+    }
+    history.recorded.remove(&entity);
+
+    commands.entity(entity).despawn_recursive();
+    history.push(vec![Edit::Presence {
+        entity,
+        spawned: false,
+        saved: Some(saved),
+    }]);
+}
+
+fn shortcuts(mut commands: Commands, keys: Res<ButtonInput<KeyCode>>, mut contexts: EguiContexts) {
+    // Leave the keys to text fields being typed in
+    if contexts.ctx_mut().wants_keyboard_input() || !keys.just_pressed(KeyCode::KeyZ) {
+        return;
+    }
+
+    let modifier = keys.any_pressed([
+        KeyCode::ControlLeft,
+        KeyCode::ControlRight,
+        KeyCode::SuperLeft,
+        KeyCode::SuperRight,
+    ]);
+    if !modifier {
+        return;
+    }
+
+    if keys.any_pressed([KeyCode::ShiftLeft, KeyCode::ShiftRight]) {
+        commands.trigger(Redo);
+    } else {
+        commands.trigger(Undo);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        shape::Shape,
+        spiro::{Radius, Settings, Side},
+    };
+    use bevy::ecs::{system::RunSystemOnce, world::CommandQueue};
+    use std::time::Duration;
+
+    /// A world with a fixed gear and a rotating gear on it, returning the rotating one
+    fn setup() -> (World, Entity) {
+        let mut world = World::new();
+        world.insert_resource(Settings::default());
+        world.insert_resource(Time::<()>::default());
+        world.insert_resource(History {
+            resync: true,
+            ..default()
+        });
+        world.observe(undo);
+        world.observe(redo);
+
+        let fixed = FixedGear {
+            position: Vec2::ZERO,
+            shape: Shape::Ring { radius: 96.0 },
+            color: Srgba::RED,
+            teeth: None,
+            rotating: vec![RotatingGear {
+                rotation: 0.0,
+                speed: 8.0,
+                radius: 24.0,
+                side: Side::Inside,
+                teeth: None,
+                color: Srgba::BLUE,
+                paused: false,
+                pens: Vec::new(),
+                rotating: Vec::new(),
+            }],
+        };
+        let mut queue = CommandQueue::default();
+        let (_fixed, children) = fixed.spawn(&mut Commands::new(&mut queue, &world));
+        queue.apply(&mut world);
+        world.flush();
+
+        (world, children[0])
+    }
+
+    /// Let `seconds` go by and record the edits made in the meantime
+    fn record(world: &mut World, seconds: f32, held: bool) {
+        world
+            .resource_mut::<Time>()
+            .advance_by(Duration::from_secs_f32(seconds));
+        world.run_system_once_with(held, record_edits);
+    }
+
+    /// Record until the gears have settled down
+    fn settle(world: &mut World) {
+        record(world, 0.0, false);
+        record(world, 2.0 * SETTLE_SECONDS, false);
+    }
+
+    fn radius(world: &World, gear: Entity) -> f32 {
+        world.get::<Radius>(gear).unwrap().0
+    }
+
+    #[test]
+    fn dragging_a_slider_is_a_single_step() {
+        let (mut world, gear) = setup();
+        settle(&mut world);
+        assert!(!world.resource::<History>().can_undo());
+
+        // A slider sweeping through values every frame, then resting on the last one
+        for radius in [25.0, 26.0, 27.0, 28.0] {
+            world.get_mut::<Radius>(gear).unwrap().0 = radius;
+            record(&mut world, 0.1 * SETTLE_SECONDS, false);
+        }
+        settle(&mut world);
+        assert_eq!(world.resource::<History>().undo.len(), 1);
+
+        world.trigger(Undo);
+        world.flush();
+        assert_eq!(radius(&world, gear), 24.0);
+        assert!(world.resource::<History>().can_redo());
+
+        // Undoing isn't an edit of its own
+        settle(&mut world);
+        assert!(!world.resource::<History>().can_undo());
+
+        world.trigger(Redo);
+        world.flush();
+        assert_eq!(radius(&world, gear), 28.0);
+        settle(&mut world);
+        assert_eq!(world.resource::<History>().undo.len(), 1);
+        assert!(!world.resource::<History>().can_redo());
+    }
+
+    #[test]
+    fn edits_wait_for_the_pointer_to_be_let_go() {
+        let (mut world, gear) = setup();
+        settle(&mut world);
+
+        world.get_mut::<Radius>(gear).unwrap().0 = 30.0;
+        record(&mut world, 0.0, true);
+        record(&mut world, 10.0 * SETTLE_SECONDS, true);
+        world.get_mut::<Radius>(gear).unwrap().0 = 40.0;
+        record(&mut world, 0.0, true);
+        record(&mut world, 10.0 * SETTLE_SECONDS, true);
+        assert!(!world.resource::<History>().can_undo());
+
+        record(&mut world, 0.0, false);
+        assert_eq!(world.resource::<History>().undo.len(), 1);
+
+        world.trigger(Undo);
+        world.flush();
+        assert_eq!(radius(&world, gear), 24.0);
+    }
+}
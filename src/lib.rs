@@ -3,8 +3,19 @@
 use bevy_debug_text_overlay::OverlayPlugin as DebugTextPlugin;
 use bevy_egui::EguiPlugin;
 
+mod camera;
 mod dragging;
+mod export;
+mod file;
+mod history;
+mod preset;
+mod random;
+mod scene;
+mod shape;
+#[cfg(any(target_arch = "wasm32", test))]
+mod share;
 mod spiro;
+mod stroke;
 mod ui;
 
 pub mod prelude {
@@ -46,13 +57,18 @@ impl Plugin for LibPlugin {
             .add_plugins((
                 //
                 spiro::plugin,
+                random::plugin,
+                stroke::plugin,
+                export::plugin,
+                scene::plugin,
+                history::plugin,
+                preset::plugin,
                 ui::plugin,
                 dragging::plugin,
-            ))
-            .add_systems(Startup, spawn_camera);
-    }
-}
+                camera::plugin,
+            ));
 
-fn spawn_camera(mut commands: Commands) {
-    commands.spawn(Camera2dBundle::default());
+        #[cfg(target_arch = "wasm32")]
+        app.add_plugins(share::plugin);
+    }
 }
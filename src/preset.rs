@@ -0,0 +1,182 @@
+use crate::{
+    file,
+    prelude::*,
+    scene::{FixedGear, RotatingGear, SavedPen, SceneGears},
+    shape::Shape,
+    spiro::{FixedGearBundle, PenBundle, RotatingGearBundle, Settings, Teeth},
+};
+use serde::{Deserialize, Serialize};
+
+/// Tooth counts of the rings in a typical kit
+pub const RING_TEETH: [u32; 5] = [96, 105, 120, 144, 150];
+
+/// Tooth counts of the wheels in a typical kit
+pub const WHEEL_TEETH: [u32; 18] = [
+    24, 30, 32, 36, 40, 42, 45, 48, 50, 52, 56, 60, 63, 64, 72, 75, 80, 84,
+];
+
+/// Distance between neighbouring pen holes of a wheel, in teeth
+const HOLE_SPACING: f32 = 2.0;
+
+/// Name the saved presets are stored under between runs
+const SAVED: &str = "presets.ron";
+
+/// Well known patterns drawn with a kit
+pub const PATTERNS: [(&str, Kit); 8] = [
+    ("Clover", Kit::new(96, 24, 2)),
+    ("Trefoil", Kit::new(96, 32, 3)),
+    ("Pentagram", Kit::new(105, 42, 1)),
+    ("Heptagram", Kit::new(105, 45, 1)),
+    ("Lace", Kit::new(105, 63, 6)),
+    ("Rosette", Kit::new(96, 60, 2)),
+    ("Web", Kit::new(105, 75, 5)),
+    ("Daisy", Kit::new(96, 84, 3)),
+];
+
+pub(super) fn plugin(app: &mut App) {
+    app //
+        .insert_resource(Presets {
+            saved: load_saved(),
+            ..default()
+        })
+        .observe(save_preset)
+        .observe(remove_preset);
+}
+
+/// Save every gear as a preset with the given name
+#[derive(Event)]
+pub struct SavePreset(pub String);
+
+/// Remove the saved preset at the given index
+#[derive(Event)]
+pub struct RemovePreset(pub usize);
+
+/// Presets saved by the user, along with the kit picked in the sidebar
+#[derive(Resource, Default)]
+pub struct Presets {
+    pub saved: Vec<Preset>,
+    pub kit: Kit,
+    /// Name of the next saved preset
+    pub name: String,
+}
+
+/// Gears that can be added all at once
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Preset {
+    pub name: String,
+    pub fixed: Vec<FixedGear>,
+}
+
+impl Preset {
+    pub fn spawn(&self, commands: &mut Commands) {
+        for fixed in &self.fixed {
+            fixed.spawn(commands);
+        }
+    }
+}
+
+/// A wheel rolling inside a ring from a kit, with the pen in one of its holes
+#[derive(Clone, Copy, PartialEq)]
+pub struct Kit {
+    pub ring: u32,
+    pub wheel: u32,
+    /// Pen hole, counted from 1 next to the rim towards the center
+    pub hole: u32,
+}
+
+impl Default for Kit {
+    fn default() -> Self {
+        Self::new(96, 36, 1)
+    }
+}
+
+impl Kit {
+    pub const fn new(ring: u32, wheel: u32, hole: u32) -> Self {
+        Self { ring, wheel, hole }
+    }
+
+    /// Number of pen holes in a wheel
+    pub fn holes(wheel: u32) -> u32 {
+        ((0.5 * wheel as f32 / HOLE_SPACING) as u32).saturating_sub(1)
+    }
+
+    pub fn preset(&self, name: String, settings: &Settings) -> Preset {
+        let fixed = FixedGearBundle::default();
+        let rotating = RotatingGearBundle::default();
+        let default_pen = PenBundle::default();
+
+        let radius = settings.teeth_radius(Teeth(self.wheel));
+        let hole = self.hole.clamp(1, Self::holes(self.wheel).max(1));
+        let pen = (radius - hole as f32 * HOLE_SPACING * settings.tooth_size).max(0.0);
+
+        Preset {
+            name,
+            fixed: vec![FixedGear {
+                position: Vec2::ZERO,
+                shape: Shape::Ring {
+                    radius: settings.teeth_radius(Teeth(self.ring)),
+                },
+                color: fixed.gear_color.0,
+                teeth: Some(self.ring),
+                rotating: vec![RotatingGear {
+                    rotation: 0.0,
+                    speed: rotating.speed.0,
+                    radius,
+                    side: rotating.side,
+                    teeth: Some(self.wheel),
+                    color: rotating.gear_color.0,
+                    paused: false,
+                    pens: vec![SavedPen {
+                        distance: pen,
+                        angle: default_pen.pen_angle.0,
+                        width: default_pen.pen_width.0,
+                        style: default_pen.pen_style,
+                        color: default_pen.line_color.0,
+                        up: false,
+                        line: None,
+                    }],
+                    rotating: Vec::new(),
+                }],
+            }],
+        }
+    }
+}
+
+fn save_preset(trigger: Trigger<SavePreset>, mut presets: ResMut<Presets>, scene: SceneGears) {
+    let SavePreset(name) = trigger.event();
+    let name = match name.trim() {
+        "" => format!("Preset {}", presets.saved.len() + 1),
+        name => name.to_string(),
+    };
+
+    let fixed = scene.snapshot(false).fixed;
+    presets.saved.push(Preset { name, fixed });
+    store_saved(&presets.saved);
+}
+
+fn remove_preset(trigger: Trigger<RemovePreset>, mut presets: ResMut<Presets>) {
+    let RemovePreset(i) = *trigger.event();
+    if i < presets.saved.len() {
+        presets.saved.remove(i);
+        store_saved(&presets.saved);
+    }
+}
+
+/// Presets saved in earlier runs
+fn load_saved() -> Vec<Preset> {
+    let Some(text) = file::load(SAVED) else {
+        return Vec::new();
+    };
+    ron::from_str(&text).unwrap_or_else(|err| {
+        error!("Failed to load saved presets: {err}");
+        Vec::new()
+    })
+}
+
+/// Keep the saved presets for later runs
+fn store_saved(saved: &[Preset]) {
+    match ron::to_string(saved) {
+        Ok(text) => file::store(SAVED, &text),
+        Err(err) => error!("Failed to store saved presets: {err}"),
+    }
+}
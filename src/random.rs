@@ -0,0 +1,298 @@
+use crate::{
+    prelude::*,
+    preset::{RING_TEETH, WHEEL_TEETH},
+    shape::{distance_to_segment, Shape},
+    spiro::{
+        gcd, FixedGearBundle, GearColor, LineColor, Pen, PenBundle, Radius, RotatingGearBundle,
+        Settings, Teeth,
+    },
+};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use std::ops::Range;
+
+/// Most laps a pleasing pattern takes to close
+const MAX_LAPS: u32 = 8;
+
+/// Lobes of pleasing patterns, fewer look plain and more look like noise
+const LOBES: Range<u32> = 3..31;
+
+pub(super) fn plugin(app: &mut App) {
+    app.insert_resource(Random::default());
+}
+
+/// Seed a random gear was made from, making it again with the same seed gives the same gear
+#[derive(Component, Clone, Copy)]
+pub struct Seed(pub u32);
+
+/// How random gears are picked
+#[derive(Clone, Copy, PartialEq, Eq, Default)]
+pub enum RandomMode {
+    /// Anything within the ranges
+    Free,
+    /// Kit gears with tooth ratios that close after a few laps, the wheel inside the ring and the
+    /// pen inside the wheel, colored from one palette
+    #[default]
+    Pleasing,
+}
+
+/// Where random gears come from
+#[derive(Resource)]
+pub struct Random {
+    /// Seed of the next random gear, a new one is drawn after each use. Seeds are kept to 32 bits so
+    /// that editing them as a number in the UI is exact
+    pub seed: u32,
+    pub mode: RandomMode,
+    pub ranges: RandomRanges,
+    /// Draws the seeds themselves
+    seeds: StdRng,
+}
+
+impl Default for Random {
+    fn default() -> Self {
+        let mut seeds = StdRng::seed_from_u64(rand::random());
+        Self {
+            seed: seeds.gen(),
+            mode: RandomMode::default(),
+            ranges: RandomRanges::default(),
+            seeds,
+        }
+    }
+}
+
+impl Random {
+    /// Generator for the next random gear along with its seed, moving on to a new seed
+    pub fn next(&mut self) -> (StdRng, Seed) {
+        let seed = self.seed;
+        self.seed = self.seeds.gen();
+        (StdRng::seed_from_u64(seed.into()), Seed(seed))
+    }
+
+    /// Spawn a random fixed gear somewhere within `bounds`, with a rotating gear
+    pub fn spawn_spirograph(&mut self, commands: &mut Commands, bounds: Rect, settings: &Settings) {
+        let (mut rng, seed) = self.next();
+        let mut fixed = FixedGearBundle::rand(&mut rng, &self.ranges);
+
+        let mut ring = None;
+        let mut wheel = None;
+        if self.mode == RandomMode::Pleasing {
+            let teeth = Teeth(*RING_TEETH.choose(&mut rng).unwrap());
+            fixed.shape = Shape::Ring {
+                radius: settings.teeth_radius(teeth),
+            };
+            ring = Some(teeth);
+
+            let [fixed_color, wheel_color, line_color] = palette(&mut rng, None);
+            fixed.gear_color = GearColor(fixed_color);
+
+            wheel = self
+                .pleasing_wheel(&mut rng, teeth, teeth.0 - 1, settings)
+                .map(|(mut rotating, mut pen, teeth)| {
+                    rotating.gear_color = GearColor(wheel_color);
+                    pen.line_color = LineColor(line_color);
+                    (rotating, pen, Some(teeth))
+                });
+        }
+        let (rotating, pen, teeth) = wheel.unwrap_or_else(|| self.free_wheel(&mut rng));
+
+        // Placed last and always with the same draws, so that the view doesn't change the gears
+        let fraction = Vec2::new(rng.gen(), rng.gen());
+        fixed.transform_bundle.local.translation =
+            (bounds.min + fraction * bounds.size()).extend(0.0);
+
+        let mut entity = commands.spawn((fixed, seed));
+        if let Some(ring) = ring {
+            entity.insert(ring);
+        }
+        entity.with_children(|parent| {
+            let mut gear = parent.spawn(rotating);
+            if let Some(teeth) = teeth {
+                gear.insert(teeth);
+            }
+            gear.with_children(|gear| {
+                gear.spawn(pen);
+            });
+        });
+    }
+
+    /// Spawn a random rotating gear rolling on `parent`, a fixed gear or another rotating gear
+    pub fn spawn_gear(
+        &mut self,
+        commands: &mut Commands,
+        parent: Entity,
+        (shape, GearColor(color), teeth): (&Shape, &GearColor, Option<&Teeth>),
+        settings: &Settings,
+    ) {
+        let (mut rng, seed) = self.next();
+
+        // Treat gears without teeth as if they had as many as fit around them, and leave
+        // some room for the wheel to roll inside
+        let outline = shape.outline();
+        let ring = teeth.copied().unwrap_or_else(|| {
+            Teeth((outline.perimeter() / settings.teeth_perimeter(Teeth(1))).round() as u32)
+        });
+        let inradius = outline
+            .polyline()
+            .windows(2)
+            .map(|edge| distance_to_segment(Vec2::ZERO, edge[0], edge[1]))
+            .fold(f32::INFINITY, f32::min);
+        let max_teeth = (0.9 * inradius / settings.teeth_radius(Teeth(1))) as u32;
+
+        let wheel = match self.mode {
+            RandomMode::Free => None,
+            RandomMode::Pleasing => self
+                .pleasing_wheel(&mut rng, ring, max_teeth, settings)
+                .map(|(mut rotating, mut pen, wheel_teeth)| {
+                    let [_, wheel_color, line_color] =
+                        palette(&mut rng, Some(Hsla::from(*color).hue));
+                    rotating.gear_color = GearColor(wheel_color);
+                    pen.line_color = LineColor(line_color);
+                    // Gears without teeth keep the size of the wheel but roll freely
+                    (rotating, pen, teeth.and(Some(wheel_teeth)))
+                }),
+        };
+        let (rotating, pen, teeth) = wheel.unwrap_or_else(|| self.free_wheel(&mut rng));
+
+        let mut entity = commands.spawn((rotating, seed));
+        if let Some(teeth) = teeth {
+            entity.insert(teeth);
+        }
+        entity
+            .with_children(|gear| {
+                gear.spawn(pen);
+            })
+            .set_parent(parent);
+    }
+
+    /// A rotating gear with a pen anywhere within the ranges
+    fn free_wheel(&self, rng: &mut impl Rng) -> (RotatingGearBundle, PenBundle, Option<Teeth>) {
+        let rotating = RotatingGearBundle::rand(rng, &self.ranges);
+        let pen = PenBundle::rand(rng, &self.ranges);
+        (rotating, pen, None)
+    }
+
+    /// A kit wheel with at most `max_teeth` that draws a pleasing pattern inside `ring`, if any
+    fn pleasing_wheel(
+        &self,
+        rng: &mut impl Rng,
+        ring: Teeth,
+        max_teeth: u32,
+        settings: &Settings,
+    ) -> Option<(RotatingGearBundle, PenBundle, Teeth)> {
+        let candidates: Vec<_> = WHEEL_TEETH
+            .iter()
+            .copied()
+            .filter(|&wheel| {
+                let divisor = gcd(ring.0, wheel);
+                wheel <= max_teeth
+                    && wheel / divisor <= MAX_LAPS
+                    && LOBES.contains(&(ring.0 / divisor))
+            })
+            .collect();
+        let teeth = Teeth(*candidates.choose(rng)?);
+
+        // Pens sit in holes some way between the center and the rim of the wheel
+        let radius = settings.teeth_radius(teeth);
+        let mut rotating = RotatingGearBundle::rand(rng, &self.ranges);
+        rotating.radius = Radius(radius);
+        let pen = PenBundle {
+            pen: Pen(rng.gen_range(0.3..0.9) * radius),
+            ..default()
+        };
+
+        Some((rotating, pen, teeth))
+    }
+}
+
+/// Colors for a fixed gear, a rotating gear and its line, spread around `hue` or a random one
+fn palette(rng: &mut impl Rng, hue: Option<f32>) -> [Srgba; 3] {
+    let hue = hue.unwrap_or_else(|| rng.gen_range(0.0..360.0));
+    let spread = rng.gen_range(20.0..45.0);
+
+    [
+        Hsla::hsl(hue, 0.5, 0.45).into(),
+        Hsla::hsl((hue + spread) % 360.0, 0.6, 0.55).into(),
+        Hsla::hsl((hue + 2.0 * spread) % 360.0, 0.85, 0.6).into(),
+    ]
+}
+
+/// Bounds of the parameters of random gears
+pub struct RandomRanges {
+    pub fixed_radius: Range<f32>,
+    pub radius: Range<f32>,
+    pub pen: Range<f32>,
+    pub speed: Range<f32>,
+}
+
+impl Default for RandomRanges {
+    fn default() -> Self {
+        Self {
+            fixed_radius: 1.0..256.0,
+            radius: 1.0..128.0,
+            pen: 0.0..64.0,
+            speed: 0.1..16.0,
+        }
+    }
+}
+
+/// A value in `range`, or its start if it's empty
+pub fn pick(rng: &mut impl Rng, range: &Range<f32>) -> f32 {
+    if range.is_empty() {
+        range.start
+    } else {
+        rng.gen_range(range.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::spiro::{Rotation, Speed};
+    use bevy::ecs::world::CommandQueue;
+
+    /// Spawn a spirograph from `seed` within `bounds` and read back what makes up its drawing
+    fn spawn(seed: u32, mode: RandomMode, bounds: Rect) -> Vec<(f32, f32, f32, Srgba)> {
+        let mut world = World::new();
+        let mut queue = CommandQueue::default();
+        let mut random = Random {
+            seed,
+            mode,
+            ..default()
+        };
+        random.spawn_spirograph(
+            &mut Commands::new(&mut queue, &world),
+            bounds,
+            &Settings::default(),
+        );
+        queue.apply(&mut world);
+
+        let mut parameters = Vec::new();
+        for (shape, GearColor(color)) in world.query::<(&Shape, &GearColor)>().iter(&world) {
+            parameters.push((shape.outline().perimeter(), 0.0, 0.0, *color));
+        }
+        for (radius, rotation, speed, GearColor(color)) in world
+            .query::<(&Radius, &Rotation, &Speed, &GearColor)>()
+            .iter(&world)
+        {
+            parameters.push((radius.0, rotation.0, speed.0, *color));
+        }
+        for (pen, LineColor(color)) in world.query::<(&Pen, &LineColor)>().iter(&world) {
+            parameters.push((pen.0, 0.0, 0.0, *color));
+        }
+        parameters
+    }
+
+    #[test]
+    fn a_seed_makes_the_same_spirograph_anywhere() {
+        let here = Rect::from_center_size(Vec2::ZERO, Vec2::splat(800.0));
+        let there = Rect::from_center_size(Vec2::new(5000.0, -300.0), Vec2::splat(40.0));
+        let nowhere = Rect::from_center_size(Vec2::ZERO, Vec2::ZERO);
+
+        for mode in [RandomMode::Free, RandomMode::Pleasing] {
+            let parameters = spawn(1234, mode, here);
+            assert_eq!(parameters.len(), 3);
+            assert_eq!(parameters, spawn(1234, mode, here));
+            assert_eq!(parameters, spawn(1234, mode, there));
+            assert_eq!(parameters, spawn(1234, mode, nowhere));
+        }
+    }
+}
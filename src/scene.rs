@@ -0,0 +1,715 @@
+use crate::{
+    file,
+    prelude::*,
+    shape::Shape,
+    spiro::{
+        Fixed, FixedGearBundle, GearColor, Line, LineColor, Paused, Pen, PenAngle, PenBundle,
+        PenStyle, PenUp, PenWidth, Radius, RotatingGearBundle, Rotation, Sampler, Settings, Side,
+        Speed, Teeth,
+    },
+};
+use bevy::ecs::system::{EntityCommands, SystemParam};
+use serde::{Deserialize, Serialize};
+use std::{
+    error::Error,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Mutex,
+    },
+};
+
+/// Version of the scene format written by this build, bumped whenever older files need migrating
+pub const VERSION: u32 = 2;
+
+pub(super) fn plugin(app: &mut App) {
+    let (sender, receiver) = mpsc::channel();
+
+    app //
+        .insert_resource(SceneOptions::default())
+        .insert_resource(Opened {
+            sender,
+            receiver: Mutex::new(receiver),
+        })
+        .add_systems(Update, load_opened)
+        .observe(save_scene)
+        .observe(open_scene);
+}
+
+#[derive(Resource, Default)]
+pub struct SceneOptions {
+    /// Save the drawn lines along with the gears
+    pub lines: bool,
+}
+
+/// Save every gear and the settings to a file
+#[derive(Event)]
+pub struct SaveScene;
+
+/// Replace every gear and the settings with those from a file the user picks
+#[derive(Event)]
+pub struct OpenScene;
+
+/// Contents of opened files, which arrive some time after they were picked
+#[derive(Resource)]
+struct Opened {
+    sender: Sender<Vec<u8>>,
+    receiver: Mutex<Receiver<Vec<u8>>>,
+}
+
+/// Serializable copy of every gear and the settings
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Snapshot {
+    pub version: u32,
+    pub settings: Settings,
+    pub fixed: Vec<FixedGear>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct FixedGear {
+    pub position: Vec2,
+    pub shape: Shape,
+    pub color: Srgba,
+    #[serde(default)]
+    pub teeth: Option<u32>,
+    #[serde(default)]
+    pub rotating: Vec<RotatingGear>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct RotatingGear {
+    pub rotation: f32,
+    pub speed: f32,
+    pub radius: f32,
+    pub side: Side,
+    #[serde(default)]
+    pub teeth: Option<u32>,
+    pub color: Srgba,
+    #[serde(default)]
+    pub paused: bool,
+    #[serde(default)]
+    pub pens: Vec<SavedPen>,
+    /// Gears rolling on this one
+    #[serde(default)]
+    pub rotating: Vec<RotatingGear>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct SavedPen {
+    pub distance: f32,
+    #[serde(default)]
+    pub angle: f32,
+    pub width: f32,
+    pub style: PenStyle,
+    pub color: Srgba,
+    #[serde(default)]
+    pub up: bool,
+    #[serde(default)]
+    pub line: Option<SavedLine>,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq)]
+pub struct SavedLine {
+    /// Rotation the line started at
+    pub start: f32,
+    pub points: Vec<Vec2>,
+}
+
+impl Snapshot {
+    pub fn to_ron(&self) -> Result<String, ron::Error> {
+        let config = ron::ser::PrettyConfig::new().depth_limit(5);
+        ron::ser::to_string_pretty(self, config)
+    }
+
+    /// Read a scene written by this or an earlier version
+    pub fn from_ron(text: &str) -> Result<Self, Box<dyn Error>> {
+        #[derive(Deserialize)]
+        struct Header {
+            version: u32,
+        }
+
+        let Header { version } = ron::from_str(text)?;
+        match version {
+            1 => Ok(ron::from_str::<v1::Snapshot>(text)?.into()),
+            VERSION => Ok(ron::from_str(text)?),
+            _ => Err(format!("unsupported scene version {version}").into()),
+        }
+    }
+
+    /// Spawn the gears and restore the drawing settings of the snapshot, leaving the sidebar and
+    /// gizmos as they are
+    pub fn spawn(&self, commands: &mut Commands) {
+        let settings = self.settings.clone();
+        commands.add(move |world: &mut World| {
+            world
+                .get_resource_or_insert_with(Settings::default)
+                .restore_drawing(&settings);
+        });
+
+        for fixed in &self.fixed {
+            fixed.spawn(commands);
+        }
+    }
+}
+
+impl FixedGear {
+    /// Spawn the gear with its rotating descendants, returning its entity and theirs
+    pub fn spawn(&self, commands: &mut Commands) -> (Entity, Vec<Entity>) {
+        let mut entity = commands.spawn(FixedGearBundle {
+            shape: self.shape.clone(),
+            gear_color: GearColor(self.color),
+            transform_bundle: TransformBundle::from_transform(Transform::from_translation(
+                self.position.extend(0.0),
+            )),
+            ..default()
+        });
+        if let Some(teeth) = self.teeth {
+            entity.insert(Teeth(teeth));
+        }
+
+        let mut children = Vec::new();
+        entity.with_children(|parent| {
+            children = self
+                .rotating
+                .iter()
+                .flat_map(|gear| gear.spawn(parent))
+                .collect();
+        });
+
+        (entity.id(), children)
+    }
+
+    /// Overwrite the parameters of an existing fixed gear, leaving its children alone
+    pub fn apply(&self, entity: &mut EntityCommands) {
+        entity.insert((
+            Transform::from_translation(self.position.extend(0.0)),
+            self.shape.clone(),
+            GearColor(self.color),
+        ));
+        match self.teeth {
+            Some(teeth) => entity.insert(Teeth(teeth)),
+            None => entity.remove::<Teeth>(),
+        };
+    }
+}
+
+impl RotatingGear {
+    /// Spawn the gear with its pens and the gears rolling on it, returning its entity followed by
+    /// those of its pens and then those of the other gears and their descendants in turn
+    pub fn spawn(&self, parent: &mut ChildBuilder) -> Vec<Entity> {
+        let mut entity = parent.spawn(RotatingGearBundle {
+            rotation: Rotation(self.rotation),
+            speed: Speed(self.speed),
+            radius: Radius(self.radius),
+            side: self.side,
+            gear_color: GearColor(self.color),
+            ..default()
+        });
+        if let Some(teeth) = self.teeth {
+            entity.insert(Teeth(teeth));
+        }
+        if self.paused {
+            entity.insert(Paused);
+        }
+
+        let mut entities = vec![entity.id()];
+        entity.with_children(|parent| {
+            entities.extend(self.pens.iter().map(|pen| pen.spawn(parent, self.rotation)));
+            entities.extend(self.rotating.iter().flat_map(|gear| gear.spawn(parent)));
+        });
+        entities
+    }
+
+    /// Overwrite the parameters of an existing rotating gear, leaving its rotation, pause and
+    /// children alone
+    pub fn apply(&self, entity: &mut EntityCommands) {
+        entity.insert((
+            Speed(self.speed),
+            Radius(self.radius),
+            self.side,
+            GearColor(self.color),
+        ));
+        match self.teeth {
+            Some(teeth) => entity.insert(Teeth(teeth)),
+            None => entity.remove::<Teeth>(),
+        };
+    }
+}
+
+impl SavedPen {
+    /// Spawn the pen on a gear turned to `rotation`
+    pub fn spawn(&self, parent: &mut ChildBuilder, rotation: f32) -> Entity {
+        let (line, sampler) = match &self.line {
+            Some(line) => (line.points.clone(), Sampler::resumed(line.start, rotation)),
+            None => (Vec::new(), Sampler::default()),
+        };
+
+        let mut entity = parent.spawn(PenBundle {
+            pen: Pen(self.distance),
+            pen_angle: PenAngle(self.angle),
+            pen_width: PenWidth(self.width),
+            pen_style: self.style,
+            line_color: LineColor(self.color),
+            line: Line(line),
+            sampler,
+            ..default()
+        });
+        if self.up {
+            entity.insert(PenUp);
+        }
+
+        entity.id()
+    }
+
+    /// Overwrite the parameters of an existing pen, leaving its line alone
+    pub fn apply(&self, entity: &mut EntityCommands) {
+        entity.insert((
+            Pen(self.distance),
+            PenAngle(self.angle),
+            PenWidth(self.width),
+            self.style,
+            LineColor(self.color),
+        ));
+        if self.up {
+            entity.insert(PenUp);
+        } else {
+            entity.remove::<PenUp>();
+        }
+    }
+}
+
+/// Everything a [`Snapshot`] is taken from and restored to
+#[derive(SystemParam)]
+pub struct SceneGears<'w, 's> {
+    q_fixed: Query<
+        'w,
+        's,
+        (
+            Entity,
+            &'static Transform,
+            &'static Shape,
+            &'static GearColor,
+            Option<&'static Teeth>,
+            Option<&'static Children>,
+        ),
+        With<Fixed>,
+    >,
+    q_rotating: Query<
+        'w,
+        's,
+        (
+            &'static Rotation,
+            &'static Speed,
+            &'static Radius,
+            &'static Side,
+            &'static GearColor,
+            Option<&'static Teeth>,
+            Option<&'static Paused>,
+            Option<&'static Children>,
+        ),
+    >,
+    q_pens: Query<
+        'w,
+        's,
+        (
+            &'static Pen,
+            &'static PenAngle,
+            &'static PenWidth,
+            &'static PenStyle,
+            &'static LineColor,
+            Option<&'static PenUp>,
+            &'static Line,
+            &'static Sampler,
+        ),
+    >,
+    settings: Res<'w, Settings>,
+}
+
+impl SceneGears<'_, '_> {
+    /// Copy the current gears and settings, with their lines if `lines` is set
+    pub fn snapshot(&self, lines: bool) -> Snapshot {
+        let fixed = self
+            .q_fixed
+            .iter()
+            .filter_map(|(entity, ..)| self.fixed_gear(entity, lines))
+            .map(|(fixed, _children)| fixed)
+            .collect();
+
+        Snapshot {
+            version: VERSION,
+            settings: self.settings.clone(),
+            fixed,
+        }
+    }
+
+    /// Copy the fixed gear `entity` with its rotating descendants, along with the entities of
+    /// those
+    pub fn fixed_gear(&self, entity: Entity, lines: bool) -> Option<(FixedGear, Vec<Entity>)> {
+        let (_entity, transform, shape, gear_color, teeth, children) =
+            self.q_fixed.get(entity).ok()?;
+        let (rotating, children) = self.rotating_children(children, lines);
+
+        let fixed = FixedGear {
+            position: transform.translation.xy(),
+            shape: shape.clone(),
+            color: gear_color.0,
+            teeth: teeth.map(|teeth| teeth.0),
+            rotating,
+        };
+        Some((fixed, children))
+    }
+
+    /// Copy the rotating gear `entity` with its pens and the gears rolling on it, along with the
+    /// entities of its descendants, with their lines if `lines` is set
+    pub fn rotating_gear(
+        &self,
+        entity: Entity,
+        lines: bool,
+    ) -> Option<(RotatingGear, Vec<Entity>)> {
+        let (rotation, speed, radius, &side, gear_color, teeth, paused, children) =
+            self.q_rotating.get(entity).ok()?;
+
+        // Pens come first, then the gears rolling on this one, the order they are spawned in
+        let (pen_entities, pens): (Vec<_>, Vec<_>) = children
+            .into_iter()
+            .flatten()
+            .filter_map(|&child| Some((child, self.pen(child, lines)?)))
+            .unzip();
+        let (rotating, gear_entities) = self.rotating_children(children, lines);
+
+        let gear = RotatingGear {
+            rotation: rotation.0,
+            speed: speed.0,
+            radius: radius.0,
+            side,
+            teeth: teeth.map(|teeth| teeth.0),
+            color: gear_color.0,
+            paused: paused.is_some(),
+            pens,
+            rotating,
+        };
+        Some((gear, [pen_entities, gear_entities].concat()))
+    }
+
+    /// Copy the pen `entity`, with its line if `lines` is set
+    pub fn pen(&self, entity: Entity, lines: bool) -> Option<SavedPen> {
+        let (
+            &Pen(distance),
+            &PenAngle(angle),
+            &PenWidth(width),
+            &style,
+            line_color,
+            pen_up,
+            line,
+            sampler,
+        ) = self.q_pens.get(entity).ok()?;
+
+        Some(SavedPen {
+            distance,
+            angle,
+            width,
+            style,
+            color: line_color.0,
+            up: pen_up.is_some(),
+            line: (lines && !line.0.is_empty()).then(|| SavedLine {
+                start: sampler.start,
+                points: line.0.clone(),
+            }),
+        })
+    }
+
+    /// Current rotation of the rotating gear `entity`
+    pub fn rotation(&self, entity: Entity) -> Option<f32> {
+        let (rotation, ..) = self.q_rotating.get(entity).ok()?;
+        Some(rotation.0)
+    }
+
+    /// Copy the rotating gears among `children`, along with their entities each followed by those
+    /// of its descendants
+    fn rotating_children(
+        &self,
+        children: Option<&Children>,
+        lines: bool,
+    ) -> (Vec<RotatingGear>, Vec<Entity>) {
+        let mut rotating = Vec::new();
+        let mut entities = Vec::new();
+        for &child in children.into_iter().flatten() {
+            if let Some((gear, descendants)) = self.rotating_gear(child, lines) {
+                rotating.push(gear);
+                entities.push(child);
+                entities.extend(descendants);
+            }
+        }
+        (rotating, entities)
+    }
+
+    /// Replace the current gears and settings with those of `snapshot`
+    pub fn restore(&self, commands: &mut Commands, snapshot: &Snapshot) {
+        for (entity, ..) in &self.q_fixed {
+            commands.entity(entity).despawn_recursive();
+        }
+
+        snapshot.spawn(commands);
+    }
+}
+
+fn save_scene(_trigger: Trigger<SaveScene>, scene: SceneGears, options: Res<SceneOptions>) {
+    let snapshot = scene.snapshot(options.lines);
+    match snapshot.to_ron() {
+        Ok(text) => file::save("spiro.ron".to_string(), text.into_bytes()),
+        Err(err) => error!("Failed to save scene: {err}"),
+    }
+}
+
+fn open_scene(_trigger: Trigger<OpenScene>, opened: Res<Opened>) {
+    file::open(&["ron"], opened.sender.clone());
+}
+
+fn load_opened(mut commands: Commands, opened: Res<Opened>, scene: SceneGears) {
+    let receiver = r!(opened.receiver.lock());
+    for bytes in receiver.try_iter() {
+        let snapshot = String::from_utf8(bytes)
+            .map_err(Box::<dyn Error>::from)
+            .and_then(|text| Snapshot::from_ron(&text));
+
+        match snapshot {
+            Ok(snapshot) => scene.restore(&mut commands, &snapshot),
+            Err(err) => error!("Failed to open scene: {err}"),
+        }
+    }
+}
+
+/// Scenes from before gears carried several pens, when each had exactly one
+mod v1 {
+    use super::SavedLine;
+    use crate::{
+        prelude::*,
+        shape::Shape,
+        spiro::{PenStyle, Settings, Side},
+    };
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    pub struct Snapshot {
+        settings: Settings,
+        fixed: Vec<FixedGear>,
+    }
+
+    #[derive(Deserialize)]
+    struct FixedGear {
+        position: Vec2,
+        shape: Shape,
+        color: Srgba,
+        #[serde(default)]
+        teeth: Option<u32>,
+        #[serde(default)]
+        rotating: Vec<RotatingGear>,
+    }
+
+    #[derive(Deserialize)]
+    struct RotatingGear {
+        rotation: f32,
+        speed: f32,
+        radius: f32,
+        pen: f32,
+        pen_width: f32,
+        pen_style: PenStyle,
+        side: Side,
+        #[serde(default)]
+        teeth: Option<u32>,
+        color: Srgba,
+        line_color: Srgba,
+        #[serde(default)]
+        paused: bool,
+        #[serde(default)]
+        pen_up: bool,
+        #[serde(default)]
+        line: Option<SavedLine>,
+        #[serde(default)]
+        rotating: Vec<RotatingGear>,
+    }
+
+    impl From<Snapshot> for super::Snapshot {
+        fn from(snapshot: Snapshot) -> Self {
+            Self {
+                version: super::VERSION,
+                settings: snapshot.settings,
+                fixed: snapshot.fixed.into_iter().map(Into::into).collect(),
+            }
+        }
+    }
+
+    impl From<FixedGear> for super::FixedGear {
+        fn from(gear: FixedGear) -> Self {
+            Self {
+                position: gear.position,
+                shape: gear.shape,
+                color: gear.color,
+                teeth: gear.teeth,
+                rotating: gear.rotating.into_iter().map(Into::into).collect(),
+            }
+        }
+    }
+
+    impl From<RotatingGear> for super::RotatingGear {
+        fn from(gear: RotatingGear) -> Self {
+            Self {
+                rotation: gear.rotation,
+                speed: gear.speed,
+                radius: gear.radius,
+                side: gear.side,
+                teeth: gear.teeth,
+                color: gear.color,
+                paused: gear.paused,
+                pens: vec![super::SavedPen {
+                    distance: gear.pen,
+                    angle: 0.0,
+                    width: gear.pen_width,
+                    style: gear.pen_style,
+                    color: gear.line_color,
+                    up: gear.pen_up,
+                    line: gear.line,
+                }],
+                rotating: gear.rotating.into_iter().map(Into::into).collect(),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::{system::RunSystemOnce, world::CommandQueue};
+
+    fn snapshot() -> Snapshot {
+        let wheel = RotatingGear {
+            rotation: 3.0,
+            speed: 2.0,
+            radius: 24.0,
+            side: Side::Inside,
+            teeth: Some(16),
+            color: Srgba::BLUE,
+            paused: true,
+            pens: vec![
+                SavedPen {
+                    distance: 12.0,
+                    angle: 0.5,
+                    width: 2.0,
+                    style: PenStyle::default(),
+                    color: Srgba::GREEN,
+                    up: false,
+                    line: Some(SavedLine {
+                        start: 1.0,
+                        points: vec![Vec2::ZERO, Vec2::ONE],
+                    }),
+                },
+                SavedPen {
+                    distance: 4.0,
+                    angle: 0.0,
+                    width: 1.0,
+                    style: PenStyle::default(),
+                    color: Srgba::BLACK,
+                    up: true,
+                    line: None,
+                },
+            ],
+            rotating: vec![RotatingGear {
+                rotation: 0.0,
+                speed: 5.0,
+                radius: 6.0,
+                side: Side::Outside,
+                teeth: None,
+                color: Srgba::WHITE,
+                paused: false,
+                pens: Vec::new(),
+                rotating: Vec::new(),
+            }],
+        };
+
+        Snapshot {
+            version: VERSION,
+            settings: Settings {
+                tooth_size: 2.5,
+                ..default()
+            },
+            fixed: vec![FixedGear {
+                position: Vec2::new(10.0, -20.0),
+                shape: Shape::Ring { radius: 96.0 },
+                color: Srgba::RED,
+                teeth: Some(64),
+                rotating: vec![wheel],
+            }],
+        }
+    }
+
+    /// Spawn `snapshot` into an empty world and take a new snapshot of it
+    fn respawn(snapshot: &Snapshot) -> Snapshot {
+        let mut world = World::new();
+        let mut queue = CommandQueue::default();
+        snapshot.spawn(&mut Commands::new(&mut queue, &world));
+        queue.apply(&mut world);
+
+        world.run_system_once(|scene: SceneGears| scene.snapshot(true))
+    }
+
+    #[test]
+    fn scenes_survive_saving_and_opening() {
+        let saved = snapshot();
+        let text = saved.to_ron().unwrap();
+        let opened = Snapshot::from_ron(&text).unwrap();
+        assert_eq!(opened.version, VERSION);
+        assert!(opened.settings == saved.settings);
+        assert!(opened.fixed == saved.fixed);
+
+        let respawned = respawn(&opened);
+        assert!(respawned.settings == saved.settings);
+        assert!(respawned.fixed == saved.fixed);
+    }
+
+    #[test]
+    fn version_1_scenes_get_a_pen_per_gear() {
+        let text = r#"(
+            version: 1,
+            settings: (tooth_size: 2.5),
+            fixed: [(
+                position: (10.0, -20.0),
+                shape: Ring(radius: 96.0),
+                color: (red: 1.0, green: 0.0, blue: 0.0, alpha: 1.0),
+                rotating: [(
+                    rotation: 3.0,
+                    speed: 2.0,
+                    radius: 24.0,
+                    pen: 12.0,
+                    pen_width: 2.0,
+                    pen_style: (cap: Round, join: Round),
+                    side: Inside,
+                    color: (red: 0.0, green: 0.0, blue: 1.0, alpha: 1.0),
+                    line_color: (red: 0.0, green: 1.0, blue: 0.0, alpha: 1.0),
+                )],
+            )],
+        )"#;
+
+        let opened = Snapshot::from_ron(text).unwrap();
+        assert_eq!(opened.version, VERSION);
+        assert_eq!(opened.settings.tooth_size, 2.5);
+
+        let [fixed] = &opened.fixed[..] else {
+            panic!("expected one fixed gear, got {}", opened.fixed.len());
+        };
+        assert_eq!(fixed.shape, Shape::Ring { radius: 96.0 });
+        let [wheel] = &fixed.rotating[..] else {
+            panic!("expected one rotating gear, got {}", fixed.rotating.len());
+        };
+        assert!(wheel.rotating.is_empty());
+        assert_eq!(wheel.color, Srgba::BLUE);
+        let [pen] = &wheel.pens[..] else {
+            panic!("expected one pen, got {}", wheel.pens.len());
+        };
+        assert_eq!((pen.distance, pen.width), (12.0, 2.0));
+        assert_eq!(pen.color, Srgba::GREEN);
+        assert!(!pen.up && pen.line.is_none());
+
+        // And it is written back as the current version
+        let reopened = Snapshot::from_ron(&opened.to_ron().unwrap()).unwrap();
+        assert!(reopened.fixed == opened.fixed);
+    }
+}
@@ -0,0 +1,667 @@
+use crate::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::{PI, TAU};
+
+/// Number of segments used to approximate a full turn when sampling curved parts of an outline
+const ARC_SEGMENTS: f32 = 64.0;
+
+/// Number of vertices used to approximate an ellipse
+const ELLIPSE_VERTICES: usize = 96;
+
+/// The shape of a fixed gear, in local coordinates around its transform.
+#[derive(Component, Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Shape {
+    Ring {
+        radius: f32,
+    },
+    Ellipse {
+        radii: Vec2,
+    },
+    /// A rounded rectangle, a stadium when the corner radius is half the smallest side
+    Bar {
+        size: Vec2,
+        corner: f32,
+    },
+    /// An equilateral triangle with the given circumradius
+    Triangle {
+        radius: f32,
+        corner: f32,
+    },
+    /// Any convex polygon, non-convex vertex lists are replaced by their convex hull
+    Polygon {
+        vertices: Vec<Vec2>,
+        corner: f32,
+    },
+}
+
+impl Default for Shape {
+    fn default() -> Self {
+        Self::Ring { radius: 150.0 }
+    }
+}
+
+impl Shape {
+    pub const NAMES: [&'static str; 5] = ["Ring", "Ellipse", "Bar", "Triangle", "Polygon"];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Ring { .. } => "Ring",
+            Self::Ellipse { .. } => "Ellipse",
+            Self::Bar { .. } => "Bar",
+            Self::Triangle { .. } => "Triangle",
+            Self::Polygon { .. } => "Polygon",
+        }
+    }
+
+    /// Build a shape of the named kind roughly matching the size of this one
+    pub fn with_name(&self, name: &str) -> Self {
+        let size = self.outline().extent();
+
+        match name {
+            "Ellipse" => Self::Ellipse {
+                radii: Vec2::new(size, 0.6 * size),
+            },
+            "Bar" => Self::Bar {
+                size: Vec2::new(2.0 * size, size),
+                corner: 0.5 * size,
+            },
+            "Triangle" => Self::Triangle {
+                radius: size,
+                corner: 0.2 * size,
+            },
+            "Polygon" => Self::Polygon {
+                vertices: (0..5)
+                    .map(|i| size * Vec2::from_angle(i as f32 * TAU / 5.0 + 0.5 * PI))
+                    .collect(),
+                corner: 0.1 * size,
+            },
+            _ => Self::Ring { radius: size },
+        }
+    }
+
+    /// Uniformly scale the shape around its origin
+    pub fn scaled(&self, factor: f32) -> Self {
+        match self {
+            Self::Ring { radius } => Self::Ring {
+                radius: factor * radius,
+            },
+            Self::Ellipse { radii } => Self::Ellipse {
+                radii: factor * *radii,
+            },
+            Self::Bar { size, corner } => Self::Bar {
+                size: factor * *size,
+                corner: factor * corner,
+            },
+            Self::Triangle { radius, corner } => Self::Triangle {
+                radius: factor * radius,
+                corner: factor * corner,
+            },
+            Self::Polygon { vertices, corner } => Self::Polygon {
+                vertices: vertices.iter().map(|v| factor * *v).collect(),
+                corner: factor * corner,
+            },
+        }
+    }
+
+    /// The boundary of the shape that rotating gears roll along
+    pub fn outline(&self) -> Outline {
+        match self {
+            Self::Ring { radius } => Outline::new(vec![Vec2::ZERO], *radius),
+            Self::Ellipse { radii } => Outline::new(
+                (0..ELLIPSE_VERTICES)
+                    .map(|i| *radii * Vec2::from_angle(i as f32 * TAU / ELLIPSE_VERTICES as f32))
+                    .collect(),
+                0.0,
+            ),
+            Self::Bar { size, corner } => {
+                let corner = corner.clamp(0.0, 0.5 * size.min_element());
+                let half = (0.5 * *size - Vec2::splat(corner)).max(Vec2::ZERO);
+
+                Outline::new(
+                    vec![
+                        Vec2::new(-half.x, -half.y),
+                        Vec2::new(half.x, -half.y),
+                        Vec2::new(half.x, half.y),
+                        Vec2::new(-half.x, half.y),
+                    ],
+                    corner,
+                )
+            }
+            Self::Triangle { radius, corner } => {
+                // Rounding the corners of an equilateral triangle by `corner` shrinks its core by
+                // twice that along the circumradius
+                let corner = corner.clamp(0.0, 0.5 * radius);
+                let core = (radius - 2.0 * corner).max(0.0);
+
+                Outline::new(
+                    (0..3)
+                        .map(|i| core * Vec2::from_angle(i as f32 * TAU / 3.0 + 0.5 * PI))
+                        .collect(),
+                    corner,
+                )
+            }
+            Self::Polygon { vertices, corner } => {
+                let corner = corner.max(0.0);
+                let sharp = Outline::new(vertices.clone(), 0.0);
+
+                Outline {
+                    rounding: corner,
+                    ..sharp.offset(-corner)
+                }
+            }
+        }
+    }
+}
+
+/// A convex core polygon grown by a rounding radius.
+///
+/// Every fixed shape can be described this way, and so can the path the center of a rotating
+/// gear follows when rolling along it: rolling on the outside grows the rounding by the gear
+/// radius, rolling on the inside shrinks it (and the core once the rounding runs out).
+#[derive(Clone, Debug)]
+pub struct Outline {
+    /// Convex hull in counter-clockwise order, may be a single point or a segment
+    core: Vec<Vec2>,
+    rounding: f32,
+}
+
+impl Outline {
+    pub fn new(points: Vec<Vec2>, rounding: f32) -> Self {
+        Self {
+            core: convex_hull(points),
+            rounding,
+        }
+    }
+
+    /// Grow (positive) or shrink (negative) the outline by `delta`
+    pub fn offset(&self, delta: f32) -> Self {
+        let rounding = self.rounding + delta;
+        if rounding >= 0.0 {
+            return Self {
+                core: self.core.clone(),
+                rounding,
+            };
+        }
+
+        let mut core = inset(&self.core, -rounding);
+        if core.is_empty() {
+            // Nothing fits, settle on the middle of the shape
+            core.push(self.center());
+        }
+
+        Self {
+            core,
+            rounding: 0.0,
+        }
+    }
+
+    /// Whether [`Self::offset`] by `delta` gives the parallel curve of the outline, rather than
+    /// clipping its corners away
+    pub fn offset_is_parallel(&self, delta: f32) -> bool {
+        self.rounding + delta >= 0.0
+    }
+
+    fn center(&self) -> Vec2 {
+        self.core.iter().sum::<Vec2>() / self.core.len().max(1) as f32
+    }
+
+    /// The largest distance from the origin to the outline
+    pub fn extent(&self) -> f32 {
+        self.core.iter().map(|p| p.length()).fold(0.0, f32::max) + self.rounding
+    }
+
+    /// Walk the outline as a sequence of straight edges and corner arcs, starting with the edge
+    /// leaving the first core vertex
+    fn segments(&self) -> impl Iterator<Item = Segment> + '_ {
+        let n = self.core.len();
+
+        (0..n).flat_map(move |i| {
+            let a = self.core[i];
+            let b = self.core[(i + 1) % n];
+            let c = self.core[(i + 2) % n];
+            let normal = edge_normal(a, b, self.core.len());
+            let next_normal = edge_normal(b, c, self.core.len());
+            let turn = if n == 1 {
+                TAU
+            } else {
+                normal
+                    .perp_dot(next_normal)
+                    .atan2(normal.dot(next_normal))
+                    .rem_euclid(TAU)
+            };
+
+            [
+                Segment::Edge {
+                    start: a + self.rounding * normal,
+                    end: b + self.rounding * normal,
+                },
+                Segment::Arc {
+                    center: b,
+                    radius: self.rounding,
+                    start: normal.to_angle(),
+                    turn,
+                },
+            ]
+        })
+    }
+
+    pub fn perimeter(&self) -> f32 {
+        self.segments().map(|segment| segment.length()).sum()
+    }
+
+    /// The point at `distance` along the outline, wrapping around past the perimeter
+    pub fn point_at(&self, distance: f32) -> Vec2 {
+        let perimeter = self.perimeter();
+        if perimeter <= f32::EPSILON {
+            return self.center();
+        }
+
+        let mut distance = distance.rem_euclid(perimeter);
+        for segment in self.segments() {
+            let length = segment.length();
+            if distance <= length && length > 0.0 {
+                return segment.lerp(distance / length);
+            }
+            distance -= length;
+        }
+
+        self.center()
+    }
+
+    /// Distance along the outline to its point closest to `point`, see [`Self::point_at`]
+    pub fn distance_to(&self, point: Vec2) -> f32 {
+        let mut traveled = 0.0;
+        let mut closest = (f32::INFINITY, 0.0);
+        for segment in self.segments() {
+            let length = segment.length();
+            let t = segment.closest(point);
+            let distance = segment.lerp(t).distance(point);
+            if distance < closest.0 {
+                closest = (distance, traveled + t * length);
+            }
+            traveled += length;
+        }
+
+        closest.1
+    }
+
+    /// Closed polyline approximating the outline
+    pub fn polyline(&self) -> Vec<Vec2> {
+        let mut points = Vec::new();
+        for segment in self.segments() {
+            match segment {
+                Segment::Edge { start, .. } => points.push(start),
+                // Sharp corners don't need any extra points
+                Segment::Arc { radius, .. } if radius <= 0.0 => {}
+                Segment::Arc { turn, .. } => {
+                    let steps = (turn / TAU * ARC_SEGMENTS).ceil() as usize;
+                    points.extend((0..steps).map(|i| segment.lerp(i as f32 / steps as f32)));
+                }
+            }
+        }
+
+        if let Some(&first) = points.first() {
+            points.push(first);
+        }
+        points
+    }
+
+    /// Whether `point` is inside the outline
+    pub fn contains(&self, point: Vec2) -> bool {
+        let n = self.core.len();
+        let inside_core = n > 2
+            && (0..n).all(|i| {
+                (self.core[(i + 1) % n] - self.core[i]).perp_dot(point - self.core[i]) >= 0.0
+            });
+        if inside_core {
+            return true;
+        }
+
+        let distance = (0..n)
+            .map(|i| distance_to_segment(point, self.core[i], self.core[(i + 1) % n]))
+            .fold(f32::INFINITY, f32::min);
+        distance <= self.rounding
+    }
+}
+
+enum Segment {
+    Edge {
+        start: Vec2,
+        end: Vec2,
+    },
+    Arc {
+        center: Vec2,
+        radius: f32,
+        start: f32,
+        turn: f32,
+    },
+}
+
+impl Segment {
+    fn length(&self) -> f32 {
+        match *self {
+            Self::Edge { start, end } => start.distance(end),
+            Self::Arc { radius, turn, .. } => radius * turn,
+        }
+    }
+
+    fn lerp(&self, t: f32) -> Vec2 {
+        match *self {
+            Self::Edge { start, end } => start.lerp(end, t),
+            Self::Arc {
+                center,
+                radius,
+                start,
+                turn,
+            } => center + radius * Vec2::from_angle(start + t * turn),
+        }
+    }
+
+    /// Fraction of the way along the segment of its point closest to `point`
+    fn closest(&self, point: Vec2) -> f32 {
+        match *self {
+            Self::Edge { start, end } => {
+                let direction = end - start;
+                if direction.length_squared() > 0.0 {
+                    ((point - start).dot(direction) / direction.length_squared()).clamp(0.0, 1.0)
+                } else {
+                    0.0
+                }
+            }
+            Self::Arc {
+                center,
+                start,
+                turn,
+                ..
+            } => {
+                if turn <= 0.0 {
+                    return 0.0;
+                }
+
+                // Past the end of the arc, the closest point is whichever end is nearer
+                let angle = ((point - center).to_angle() - start).rem_euclid(TAU);
+                if angle <= turn {
+                    angle / turn
+                } else if angle - turn < TAU - angle {
+                    1.0
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+}
+
+/// Outward normal of the edge from `a` to `b` of a counter-clockwise polygon
+fn edge_normal(a: Vec2, b: Vec2, vertices: usize) -> Vec2 {
+    if vertices == 1 {
+        // A single point has no edges, start the arc around it at angle zero
+        return Vec2::X;
+    }
+
+    let direction = (b - a).normalize_or_zero();
+    Vec2::new(direction.y, -direction.x)
+}
+
+pub fn distance_to_segment(point: Vec2, a: Vec2, b: Vec2) -> f32 {
+    let ab = b - a;
+    let t = if ab.length_squared() > 0.0 {
+        ((point - a).dot(ab) / ab.length_squared()).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    point.distance(a + t * ab)
+}
+
+/// Drop the points of a polyline that stray less than `tolerance` from the simplified polyline
+/// (Ramer–Douglas–Peucker), always keeping both ends
+pub fn simplify(points: &[Vec2], tolerance: f32) -> Vec<Vec2> {
+    if points.len() < 3 {
+        return points.to_vec();
+    }
+
+    let mut keep = vec![false; points.len()];
+    keep[0] = true;
+    keep[points.len() - 1] = true;
+
+    // Ranges still to be split at their farthest point, a stack rather than recursion since
+    // lines can get long
+    let mut ranges = vec![(0, points.len() - 1)];
+    while let Some((start, end)) = ranges.pop() {
+        let farthest = (start + 1..end)
+            .map(|i| {
+                (
+                    i,
+                    distance_to_segment(points[i], points[start], points[end]),
+                )
+            })
+            .max_by(|(_, a), (_, b)| a.total_cmp(b));
+
+        if let Some((i, distance)) = farthest {
+            if distance > tolerance {
+                keep[i] = true;
+                ranges.extend([(start, i), (i, end)]);
+            }
+        }
+    }
+
+    points
+        .iter()
+        .zip(keep)
+        .filter_map(|(&point, keep)| keep.then_some(point))
+        .collect()
+}
+
+/// Counter-clockwise convex hull without collinear points (Andrew's monotone chain)
+fn convex_hull(mut points: Vec<Vec2>) -> Vec<Vec2> {
+    points.sort_by(|a, b| a.x.total_cmp(&b.x).then(a.y.total_cmp(&b.y)));
+    points.dedup_by(|a, b| a.distance_squared(*b) < 1e-6);
+    if points.len() < 3 {
+        return points;
+    }
+
+    let mut hull: Vec<Vec2> = Vec::with_capacity(2 * points.len());
+    for pass in [points.clone(), points.into_iter().rev().collect()] {
+        let start = hull.len();
+        for p in pass {
+            while hull.len() >= start + 2 {
+                let a = hull[hull.len() - 2];
+                let b = hull[hull.len() - 1];
+                if (b - a).perp_dot(p - a) > 0.0 {
+                    break;
+                }
+                hull.pop();
+            }
+            hull.push(p);
+        }
+        // The last point of each pass is the first point of the next one
+        hull.pop();
+    }
+
+    hull
+}
+
+/// Move every edge of a convex counter-clockwise polygon inwards by `amount`, clipping away edges
+/// that vanish
+fn inset(core: &[Vec2], amount: f32) -> Vec<Vec2> {
+    // Points and segments have no inside to shrink into
+    if core.len() < 3 {
+        return Vec::new();
+    }
+
+    let n = core.len();
+    let mut polygon = core.to_vec();
+    for i in 0..n {
+        let a = core[i];
+        let normal = edge_normal(a, core[(i + 1) % n], n);
+        let limit = normal.dot(a) - amount;
+
+        let mut clipped = Vec::with_capacity(polygon.len() + 1);
+        for (&p, &q) in polygon.iter().zip(polygon.iter().cycle().skip(1)) {
+            let dp = normal.dot(p) - limit;
+            let dq = normal.dot(q) - limit;
+
+            if dp <= 0.0 {
+                clipped.push(p);
+            }
+            if (dp < 0.0) != (dq < 0.0) {
+                clipped.push(p.lerp(q, dp / (dp - dq)));
+            }
+        }
+
+        polygon = clipped;
+        if polygon.is_empty() {
+            break;
+        }
+    }
+
+    convex_hull(polygon)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_points_near(actual: &[Vec2], expected: &[Vec2]) {
+        assert_eq!(
+            actual.len(),
+            expected.len(),
+            "{:?} != {:?}",
+            actual,
+            expected
+        );
+        for (a, b) in actual.iter().zip(expected) {
+            assert!(a.abs_diff_eq(*b, 1e-4), "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn convex_hull_drops_inner_and_collinear_points() {
+        let points = vec![
+            Vec2::new(1.0, 1.0),
+            Vec2::new(0.0, 0.0),
+            Vec2::new(-1.0, -1.0),
+            Vec2::new(0.0, -1.0),
+            Vec2::new(-1.0, 1.0),
+            Vec2::new(1.0, -1.0),
+            Vec2::new(1.0, 1.0),
+        ];
+
+        assert_points_near(
+            &convex_hull(points),
+            &[
+                Vec2::new(-1.0, -1.0),
+                Vec2::new(1.0, -1.0),
+                Vec2::new(1.0, 1.0),
+                Vec2::new(-1.0, 1.0),
+            ],
+        );
+    }
+
+    #[test]
+    fn convex_hull_keeps_points_and_segments() {
+        assert_points_near(&convex_hull(vec![Vec2::ONE, Vec2::ONE]), &[Vec2::ONE]);
+        assert_points_near(
+            &convex_hull(vec![Vec2::X, Vec2::NEG_X]),
+            &[Vec2::NEG_X, Vec2::X],
+        );
+    }
+
+    #[test]
+    fn inset_moves_edges_inwards() {
+        let square = [
+            Vec2::new(-1.0, -1.0),
+            Vec2::new(1.0, -1.0),
+            Vec2::new(1.0, 1.0),
+            Vec2::new(-1.0, 1.0),
+        ];
+
+        assert_points_near(
+            &inset(&square, 0.5),
+            &[
+                Vec2::new(-0.5, -0.5),
+                Vec2::new(0.5, -0.5),
+                Vec2::new(0.5, 0.5),
+                Vec2::new(-0.5, 0.5),
+            ],
+        );
+    }
+
+    #[test]
+    fn inset_past_the_middle_is_empty() {
+        let triangle = [
+            Vec2::new(-1.0, 0.0),
+            Vec2::new(1.0, 0.0),
+            Vec2::new(0.0, 1.0),
+        ];
+
+        assert!(inset(&triangle, 1.0).is_empty());
+        assert!(inset(&[Vec2::NEG_X, Vec2::X], 0.1).is_empty());
+    }
+
+    #[test]
+    fn simplify_keeps_the_ends_and_drops_collinear_points() {
+        let line: Vec<_> = (0..=10)
+            .map(|i| Vec2::new(i as f32, 2.0 * i as f32))
+            .collect();
+
+        assert_points_near(&simplify(&line, 0.01), &[line[0], line[10]]);
+    }
+
+    #[test]
+    fn simplify_keeps_corners_beyond_the_tolerance() {
+        let line = [
+            Vec2::new(0.0, 0.0),
+            Vec2::new(1.0, 0.01),
+            Vec2::new(2.0, 0.0),
+            Vec2::new(2.0, 1.0),
+            Vec2::new(2.0, 2.0),
+        ];
+
+        assert_points_near(&simplify(&line, 0.1), &[line[0], line[2], line[4]]);
+        assert_points_near(
+            &simplify(&line, 0.001),
+            &[line[0], line[1], line[2], line[4]],
+        );
+    }
+
+    #[test]
+    fn simplify_leaves_short_lines_alone() {
+        let line = [Vec2::ZERO, Vec2::ONE];
+
+        assert_points_near(&simplify(&line, 10.0), &line);
+        assert!(simplify(&[], 10.0).is_empty());
+    }
+
+    #[test]
+    fn distance_to_undoes_point_at() {
+        let shapes = [
+            Shape::Ring { radius: 10.0 },
+            Shape::Bar {
+                size: Vec2::new(40.0, 20.0),
+                corner: 5.0,
+            },
+            Shape::Triangle {
+                radius: 30.0,
+                corner: 0.0,
+            },
+        ];
+
+        for shape in shapes {
+            let outline = shape.outline();
+            let perimeter = outline.perimeter();
+            for i in 0..16 {
+                let distance = perimeter * i as f32 / 16.0;
+                let found = outline.distance_to(outline.point_at(distance));
+                // The start and the end of the outline are the same point
+                let error = (found - distance).abs();
+                assert!(
+                    error.min(perimeter - error) < 1e-2,
+                    "{:?} at {}",
+                    shape,
+                    distance
+                );
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+use crate::scene::Snapshot;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use std::error::Error;
+
+// Only the encoding of scenes is built outside the web, for its tests
+#[cfg(target_arch = "wasm32")]
+use crate::{
+    history::History,
+    prelude::*,
+    scene::{RotatingGear, SceneGears},
+    spiro::{Paused, Settings},
+};
+
+/// Largest scene accepted from a URL once inflated, links are never anywhere near it
+const MAX_SCENE_BYTES: usize = 1 << 20;
+
+#[cfg(target_arch = "wasm32")]
+pub(super) fn plugin(app: &mut App) {
+    app.add_systems(Update, update_url);
+}
+
+/// The scene shared through the fragment of the page's URL, if there is one
+#[cfg(target_arch = "wasm32")]
+pub fn read() -> Option<Snapshot> {
+    let hash = web_sys::window()?.location().hash().ok()?;
+    let fragment = hash
+        .strip_prefix('#')
+        .filter(|fragment| !fragment.is_empty())?;
+
+    match decode(fragment) {
+        Ok(snapshot) => Some(snapshot),
+        Err(err) => {
+            error!("Failed to restore the scene from the URL: {err}");
+            None
+        }
+    }
+}
+
+/// Keep the fragment of the page's URL in step with the scene
+#[cfg(target_arch = "wasm32")]
+fn update_url(
+    scene: SceneGears,
+    history: Res<History>,
+    settings: Res<Settings>,
+    q_paused: Query<(), Changed<Paused>>,
+    mut unpaused: RemovedComponents<Paused>,
+    mut fragment: Local<String>,
+    mut encoded_settings: Local<Option<Settings>>,
+) {
+    // Edits to the gears show up in the history, the sidebar touches the settings every frame so
+    // they are compared instead
+    let paused = !q_paused.is_empty() || unpaused.read().count() > 0;
+    if !history.is_changed() && !paused && encoded_settings.as_ref() == Some(&*settings) {
+        return;
+    }
+    *encoded_settings = Some(settings.clone());
+
+    let mut snapshot = scene.snapshot(false);
+
+    // The gears turn all the time, only edits should change the URL
+    for fixed in &mut snapshot.fixed {
+        stop(&mut fixed.rotating);
+    }
+
+    let encoded = encode(&snapshot);
+    if encoded == *fragment {
+        return;
+    }
+
+    // Replace the URL rather than pushing it, every edit shouldn't be a step back in history
+    let url = format!("#{encoded}");
+    let replaced = web_sys::window()
+        .ok_or_else(|| "no window".into())
+        .and_then(|window| window.history())
+        .and_then(|history| {
+            history.replace_state_with_url(&wasm_bindgen::JsValue::NULL, "", Some(&url))
+        });
+    if let Err(err) = replaced {
+        error!("Failed to update the URL: {err:?}");
+    }
+
+    *fragment = encoded;
+}
+
+/// Turn `gears` and those rolling on them back to their starting rotation
+#[cfg(target_arch = "wasm32")]
+fn stop(gears: &mut [RotatingGear]) {
+    for gear in gears {
+        gear.rotation = 0.0;
+        stop(&mut gear.rotating);
+    }
+}
+
+/// Compressed and URL safe form of the scene
+fn encode(snapshot: &Snapshot) -> String {
+    let ron = ron::to_string(snapshot).unwrap_or_default();
+    let compressed = miniz_oxide::deflate::compress_to_vec(ron.as_bytes(), 9);
+    URL_SAFE_NO_PAD.encode(compressed)
+}
+
+fn decode(fragment: &str) -> Result<Snapshot, Box<dyn Error>> {
+    let compressed = URL_SAFE_NO_PAD.decode(fragment)?;
+    let ron = miniz_oxide::inflate::decompress_to_vec_with_limit(&compressed, MAX_SCENE_BYTES)
+        .map_err(|err| format!("{err:?}"))?;
+    Snapshot::from_ron(std::str::from_utf8(&ron)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        prelude::*,
+        scene::{FixedGear, RotatingGear, SavedPen, VERSION},
+        shape::Shape,
+        spiro::{PenStyle, Settings, Side},
+    };
+
+    #[test]
+    fn scenes_survive_the_url() {
+        let snapshot = Snapshot {
+            version: VERSION,
+            settings: Settings {
+                line_tolerance: 0.25,
+                ..default()
+            },
+            fixed: vec![FixedGear {
+                position: Vec2::new(-3.0, 7.5),
+                shape: Shape::Ring { radius: 96.0 },
+                color: Srgba::RED,
+                teeth: Some(64),
+                rotating: vec![RotatingGear {
+                    rotation: 0.0,
+                    speed: 8.0,
+                    radius: 30.0,
+                    side: Side::Outside,
+                    teeth: Some(20),
+                    color: Srgba::BLUE,
+                    paused: true,
+                    pens: vec![SavedPen {
+                        distance: 22.0,
+                        angle: 1.0,
+                        width: 1.5,
+                        style: PenStyle::default(),
+                        color: Srgba::GREEN,
+                        up: false,
+                        line: None,
+                    }],
+                    rotating: Vec::new(),
+                }],
+            }],
+        };
+
+        let fragment = encode(&snapshot);
+        assert!(fragment
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+
+        let decoded = decode(&fragment).unwrap();
+        assert!(decoded.settings == snapshot.settings);
+        assert!(decoded.fixed == snapshot.fixed);
+    }
+
+    #[test]
+    fn garbled_fragments_are_rejected() {
+        assert!(decode("not base64!").is_err());
+        assert!(decode(&URL_SAFE_NO_PAD.encode(b"not deflated")).is_err());
+    }
+}
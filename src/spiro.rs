@@ -1,15 +1,19 @@
 use crate::{
     dragging::{DragEnd, DragStart, Draggable},
     prelude::*,
+    random::{pick, RandomRanges},
+    shape::{distance_to_segment, Outline, Shape},
+    stroke::{Cap, Join},
 };
 use rand::Rng;
-use std::f32::consts::TAU;
+use serde::{Deserialize, Serialize};
+use std::f32::consts::{PI, TAU};
 
 #[derive(Component)]
 pub struct Fixed;
 
 #[derive(Component)]
-pub struct Rotation(f32);
+pub struct Rotation(pub f32);
 
 #[derive(Component)]
 pub struct Speed(pub f32);
@@ -20,28 +24,100 @@ pub struct Gear;
 #[derive(Component)]
 pub struct Radius(pub f32);
 
+/// Distance of a pen from the center of its gear
 #[derive(Component)]
 pub struct Pen(pub f32);
 
+impl Pen {
+    /// Position of the pen relative to the center of its gear, before the gear turns
+    pub fn offset(&self, PenAngle(angle): PenAngle) -> Vec2 {
+        Vec2::from_angle(90.0_f32.to_radians() + angle) * self.0
+    }
+}
+
+/// Angle of a pen around the center of its gear, counterclockwise from the top in radians
+#[derive(Component, Clone, Copy, PartialEq)]
+pub struct PenAngle(pub f32);
+
+/// Width of the drawn line in world units
+#[derive(Component, Clone, Copy, PartialEq)]
+pub struct PenWidth(pub f32);
+
+#[derive(Component, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct PenStyle {
+    pub cap: Cap,
+    pub join: Join,
+}
+
+/// Optional tooth count, when present the size of the gear is derived from it and
+/// [`Settings::tooth_size`].
+#[derive(Component, Clone, Copy, PartialEq, Eq)]
+pub struct Teeth(pub u32);
+
+/// Where the current [`Line`] started and the [`Rotation`] of its two most recent points
+#[derive(Component, Default)]
+pub struct Sampler {
+    pub start: f32,
+    previous: Option<f32>,
+    last: Option<f32>,
+    /// Gear of the [`Train`] whose rotation the points were sampled at, see [`Train::driver`]
+    driver: usize,
+}
+
 #[derive(Component)]
 pub struct GearColor(pub Srgba);
 
-#[derive(Component)]
+#[derive(Component, Clone, Copy)]
 pub struct LineColor(pub Srgba);
 
+impl LineColor {
+    /// Color of the line at its `index`th point, black lines cycle through the rainbow
+    pub fn at(&self, index: usize) -> Srgba {
+        if self.0 == Srgba::BLACK {
+            RAINBOW[(index / 4) % RAINBOW.len()]
+        } else {
+            self.0
+        }
+    }
+}
+
 #[derive(Component)]
 pub struct Line(pub Vec<Vec2>);
 
 #[derive(Component)]
 pub struct Paused;
 
+/// A pen lifted off the paper, which draws no line
 #[derive(Component)]
-pub struct PenPos(Vec2);
+pub struct PenUp;
 
-#[derive(Resource)]
+/// Position of a pen in the world, as of the last update
+#[derive(Component)]
+pub struct PenPos(pub Vec2);
+
+/// Which side of the gear below a rotating gear rolls along
+#[derive(Component, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Side {
+    /// Roll around the inside of the gear below (hypotrochoid)
+    #[default]
+    Inside,
+    /// Roll around the outside of the gear below (epitrochoid)
+    Outside,
+}
+
+#[derive(Resource, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
 pub struct Settings {
     pub gizmos_enabled: bool,
     pub show_sidebar: bool,
+    /// Distance between teeth along the pitch line of gears with [`Teeth`], the gear module
+    pub tooth_size: f32,
+    /// Points per turn of the rotating gear when rendering a full curve at once
+    pub render_resolution: u32,
+    /// How far a drawn line may stray from the exact curve
+    pub line_tolerance: f32,
+    /// How far a simplified line may stray from the line it was simplified from
+    pub simplify_tolerance: f32,
 }
 
 impl Default for Settings {
@@ -49,7 +125,454 @@ impl Default for Settings {
         Self {
             gizmos_enabled: true,
             show_sidebar: true,
+            tooth_size: 3.0,
+            render_resolution: 360,
+            line_tolerance: 0.1,
+            simplify_tolerance: 0.05,
+        }
+    }
+}
+
+impl Settings {
+    /// Radius of a round gear with the given number of teeth
+    pub fn teeth_radius(&self, Teeth(teeth): Teeth) -> f32 {
+        0.5 * self.tooth_size * teeth as f32
+    }
+
+    /// Perimeter of a gear with the given number of teeth
+    pub fn teeth_perimeter(&self, Teeth(teeth): Teeth) -> f32 {
+        PI * self.tooth_size * teeth as f32
+    }
+
+    /// Take the settings that change what is drawn from `other`, keeping the state of the
+    /// interface
+    pub fn restore_drawing(&mut self, other: &Settings) {
+        let Self {
+            gizmos_enabled,
+            show_sidebar,
+            ..
+        } = *self;
+        *self = Self {
+            gizmos_enabled,
+            show_sidebar,
+            ..other.clone()
+        };
+    }
+}
+
+/// When a pattern drawn by gears with [`Teeth`] starts repeating itself
+pub struct Closure {
+    /// Number of lobes in the finished pattern
+    pub lobes: u32,
+    /// Number of laps around the fixed gear before the pattern repeats
+    pub laps: u32,
+    /// How far the rotating gear turns before the pattern repeats
+    pub rotation: f32,
+}
+
+impl Closure {
+    /// Upper bound on laps when looking for the closure of gears without teeth
+    const MAX_LAPS: u32 = 128;
+
+    /// Exact closure of gears with tooth counts, which only holds while the rolling gear follows the
+    /// whole fixed shape, see [`Self::estimate`] otherwise
+    pub fn new(
+        trace: &Trace,
+        Teeth(fixed_teeth): Teeth,
+        Teeth(rotating_teeth): Teeth,
+    ) -> Option<Self> {
+        if fixed_teeth == 0 || rotating_teeth == 0 || !trace.parallel {
+            return None;
+        }
+
+        let divisor = gcd(fixed_teeth, rotating_teeth);
+        let laps = rotating_teeth / divisor;
+        let rotation = laps as f32 * trace.rotation_per_lap()?;
+
+        Some(Self {
+            lobes: fixed_teeth / divisor,
+            laps,
+            rotation,
+        })
+    }
+
+    /// Closure of gears with free radii, the first lap count that gets close to a whole number of
+    /// turns (or the closest within [`Self::MAX_LAPS`])
+    pub fn estimate(trace: &Trace) -> Option<Self> {
+        let rotation_per_lap = trace.rotation_per_lap()?;
+        let turns_per_lap = rotation_per_lap / TAU;
+
+        let error = |laps: u32| {
+            let turns = laps as f32 * turns_per_lap;
+            (turns - turns.round()).abs()
+        };
+        let laps = (1..=Self::MAX_LAPS)
+            .find(|&laps| error(laps) < 1e-3)
+            .or_else(|| (1..=Self::MAX_LAPS).min_by(|&a, &b| error(a).total_cmp(&error(b))))?;
+
+        // Each turn relative to the fixed gear adds a lobe, rolling on the inside turns against the
+        // direction of travel
+        let turns = (laps as f32 * turns_per_lap).round() as u32;
+        let lobes = match trace.side {
+            Side::Inside => turns + laps,
+            Side::Outside => turns.saturating_sub(laps),
+        };
+
+        Some(Self {
+            lobes,
+            laps,
+            rotation: laps as f32 * rotation_per_lap,
+        })
+    }
+}
+
+/// A gear of some radius rolling along a fixed shape.
+///
+/// Positions are local to the fixed gear and parameterized by the rolling gear's [`Rotation`], so
+/// any point of the curve drawn by one of its pens can be evaluated without simulating the gears.
+pub struct Trace {
+    /// Path of the center of the rolling gear
+    path: Outline,
+    radius: f32,
+    side: Side,
+    /// Whether the path runs parallel to the whole fixed shape, it cuts the corners of shapes
+    /// sharper than the rolling gear when rolling inside them
+    parallel: bool,
+}
+
+impl Trace {
+    pub fn new(fixed_shape: &Shape, radius: f32, side: Side) -> Self {
+        // The center of the rotating circle stays one radius away from the fixed shape, inside or
+        // outside of it
+        let outline = fixed_shape.outline();
+        let delta = match side {
+            Side::Inside => -radius,
+            Side::Outside => radius,
+        };
+
+        Self {
+            path: outline.offset(delta),
+            radius,
+            side,
+            parallel: outline.offset_is_parallel(delta),
+        }
+    }
+
+    /// Calculate the angle and center position of the rotating circle
+    pub fn angle_and_center(&self, rotation: f32) -> (f32, Vec2) {
+        // Rolling without slipping, the center travels the rotating circle's radius for every
+        // radian it turns
+        let distance_traveled = rotation * self.radius;
+        let center = self.path.point_at(distance_traveled);
+
+        // Rolling along the inside spins the circle against the direction of travel
+        let angle = match self.side {
+            Side::Inside => -rotation,
+            Side::Outside => rotation,
+        };
+
+        (angle, center)
+    }
+
+    /// Position at `rotation` of the pen placed at `pen` from the center, see [`Pen::offset`]
+    pub fn pen_at(&self, rotation: f32, pen: Vec2) -> Vec2 {
+        let (angle, center) = self.angle_and_center(rotation);
+        center + Vec2::from_angle(angle).rotate(pen)
+    }
+
+    /// The rotation that brings the center of the rolling gear closest to `point`, on the lap
+    /// closest to `near`
+    pub fn rotation_at(&self, point: Vec2, near: f32) -> f32 {
+        if self.radius <= 0.0 {
+            return near;
+        }
+
+        let rotation = self.path.distance_to(point) / self.radius;
+        match self.rotation_per_lap() {
+            Some(lap) => rotation + ((near - rotation) / lap).round() * lap,
+            None => rotation,
+        }
+    }
+
+    /// How far the rotating gear turns in one lap around the fixed shape
+    pub fn rotation_per_lap(&self) -> Option<f32> {
+        let rotation = self.path.perimeter() / self.radius;
+        (rotation.is_finite() && rotation > 0.0).then_some(rotation)
+    }
+
+    /// Sample the curve of the pen at `pen` from `phase` through `rotation` radians with
+    /// `resolution` points per turn
+    pub fn curve(&self, phase: f32, rotation: f32, resolution: u32, pen: Vec2) -> Vec<Vec2> {
+        let steps = (rotation / TAU * resolution as f32).ceil().max(1.0) as usize;
+        (0..=steps)
+            .map(|i| self.pen_at(phase + rotation * i as f32 / steps as f32, pen))
+            .collect()
+    }
+}
+
+/// Gears rolling on each other, the first on a fixed gear and each of the others on the one before.
+///
+/// Positions are parameterized by the [`Rotation`] of the driver, the last gear that moves, with
+/// the other gears turning along at their speeds relative to it. A pen on a single gear is traced
+/// by the rotation of that gear, and a pen on a stopped gear is still traced while the gears
+/// carrying it move.
+pub struct Train {
+    /// Position of the fixed gear
+    origin: Vec2,
+    carriers: Vec<Carrier>,
+    /// The last gear, rolling on the last carrier or the fixed gear
+    last: Carrier,
+    /// The driver counted from the last gear down, 0 when the last gear moves or nothing does
+    driver: usize,
+    /// Current rotation of the driver
+    rotation: f32,
+    /// Offset of the traced pen on the last gear, see [`Pen::offset`]
+    pen: Vec2,
+}
+
+/// A gear of a [`Train`]
+struct Carrier {
+    trace: Trace,
+    rotation: f32,
+    /// Speed relative to the driver of the train
+    ratio: f32,
+}
+
+impl Train {
+    /// The gear `entity` with the gears it rolls on down to the fixed gear, if it rolls on one,
+    /// tracing the pen at `pen` on it
+    fn new(
+        entity: Entity,
+        pen: Vec2,
+        q_gears: &Query<(&Rotation, &Speed, &Radius, &Side, Option<&Paused>, &Parent)>,
+        q_fixed: &Query<(&Transform, &Shape), With<Fixed>>,
+    ) -> Option<Self> {
+        // From the last gear down to the one rolling on the fixed gear
+        let mut gears = Vec::new();
+        let mut parent = entity;
+        let (origin, shape) = loop {
+            if let Ok((transform, shape)) = q_fixed.get(parent) {
+                break (transform.translation.xy(), shape.clone());
+            }
+            let (rotation, speed, radius, side, paused, next) = q_gears.get(parent).ok()?;
+            let speed = if paused.is_some() { 0.0 } else { speed.0 };
+            gears.push((rotation.0, speed, radius.0, *side));
+            parent = next.get();
+        };
+
+        // The last gear that moves drives the train
+        let driver = gears
+            .iter()
+            .position(|&(_, speed, ..)| speed != 0.0)
+            .unwrap_or(0);
+        let (rotation, driver_speed, ..) = gears[driver];
+
+        let mut shape = shape;
+        let mut carriers: Vec<_> = gears
+            .iter()
+            .enumerate()
+            .rev()
+            .map(|(i, &(rotation, speed, radius, side))| {
+                let trace = Trace::new(&shape, radius, side);
+                shape = Shape::Ring { radius };
+                let ratio = if i == driver {
+                    1.0
+                } else if driver_speed == 0.0 {
+                    0.0
+                } else {
+                    speed / driver_speed
+                };
+                Carrier {
+                    trace,
+                    rotation,
+                    ratio,
+                }
+            })
+            .collect();
+        let last = carriers.pop()?;
+
+        Some(Self {
+            origin,
+            carriers,
+            last,
+            driver,
+            rotation,
+            pen,
+        })
+    }
+
+    /// The last gear, if it rolls directly on the fixed gear
+    pub fn single(&self) -> Option<&Trace> {
+        self.carriers.is_empty().then_some(&self.last.trace)
+    }
+
+    /// The gear whose rotation positions are parameterized by, counted from the last gear down
+    pub fn driver(&self) -> usize {
+        self.driver
+    }
+
+    /// Current rotation of the driver
+    pub fn rotation(&self) -> f32 {
+        self.rotation
+    }
+
+    /// Position of the traced pen in the world when the driver has turned to `rotation`
+    pub fn pen_at(&self, rotation: f32) -> Vec2 {
+        let turned = rotation - self.rotation;
+
+        let mut center = self.origin;
+        let mut angle = 0.0;
+        for carrier in &self.carriers {
+            let (carrier_angle, carrier_center) = carrier
+                .trace
+                .angle_and_center(carrier.rotation + carrier.ratio * turned);
+            center += Vec2::from_angle(angle).rotate(carrier_center);
+            angle += carrier_angle;
+        }
+
+        let rotation = self.last.rotation + self.last.ratio * turned;
+        center + Vec2::from_angle(angle).rotate(self.last.trace.pen_at(rotation, self.pen))
+    }
+}
+
+impl Sampler {
+    /// Deepest subdivision of a single step
+    const MAX_DEPTH: u32 = 12;
+
+    /// Largest turn between consecutive segments before subdividing
+    const MAX_TURN: f32 = 0.1;
+
+    /// Start a new line at `rotation` of the gear `driver` of the train
+    fn restart(&mut self, rotation: f32, driver: usize) {
+        *self = Self {
+            start: rotation,
+            driver,
+            ..default()
+        };
+    }
+
+    /// Carry on from the last point of the line along the rotation of another gear of the train,
+    /// once the one it followed stops or another starts moving
+    fn follow(&mut self, driver: usize) {
+        self.previous = None;
+        self.last = None;
+        self.driver = driver;
+    }
+
+    /// A line that started at `start` and was last extended at `rotation`
+    pub fn resumed(start: f32, rotation: f32) -> Self {
+        Self {
+            start,
+            previous: None,
+            last: Some(rotation),
+            driver: 0,
+        }
+    }
+
+    /// A line that was drawn from `rotation` through a full `closure` at once
+    pub fn completed(rotation: f32, closure: f32) -> Self {
+        Self {
+            start: rotation - closure,
+            previous: None,
+            last: Some(rotation),
+            driver: 0,
+        }
+    }
+
+    /// Extend `points` along the curve up to `to`, adding more points where the curve bends and
+    /// stretching the last segment where it doesn't
+    fn extend(
+        &mut self,
+        points: &mut Vec<Vec2>,
+        pen_at: impl Fn(f32) -> Vec2,
+        to: f32,
+        tolerance: f32,
+    ) {
+        let Some(last) = self.last.filter(|_| !points.is_empty()) else {
+            points.push(pen_at(to));
+            self.previous = None;
+            self.last = Some(to);
+            return;
+        };
+        if to == last {
+            return;
+        }
+
+        // Keep going in a straight line while the curve allows it
+        if let (Some(previous), [.., a, b]) = (self.previous, points.as_slice()) {
+            let end = pen_at(to);
+            if distance_to_segment(*b, *a, end) <= tolerance
+                && is_flat(&pen_at, previous, to, tolerance)
+            {
+                *points.last_mut().unwrap() = end;
+                self.last = Some(to);
+                return;
+            }
         }
+
+        self.subdivide(points, &pen_at, last, to, tolerance, Self::MAX_DEPTH);
+    }
+
+    fn subdivide(
+        &mut self,
+        points: &mut Vec<Vec2>,
+        pen_at: &impl Fn(f32) -> Vec2,
+        from: f32,
+        to: f32,
+        tolerance: f32,
+        depth: u32,
+    ) {
+        if depth > 0 && !is_flat(pen_at, from, to, tolerance) {
+            let middle = 0.5 * (from + to);
+            self.subdivide(points, pen_at, from, middle, tolerance, depth - 1);
+            self.subdivide(points, pen_at, middle, to, tolerance, depth - 1);
+        } else {
+            points.push(pen_at(to));
+            self.previous = self.last;
+            self.last = Some(to);
+        }
+    }
+}
+
+/// Whether the curve between `from` and `to` is close enough to a straight segment
+fn is_flat(pen_at: &impl Fn(f32) -> Vec2, from: f32, to: f32, tolerance: f32) -> bool {
+    let start = pen_at(from);
+    let end = pen_at(to);
+    let samples = [0.25, 0.5, 0.75].map(|t| pen_at(from + t * (to - from)));
+
+    // Chord error
+    if samples
+        .iter()
+        .any(|&p| distance_to_segment(p, start, end) > tolerance)
+    {
+        return false;
+    }
+
+    // Turning angle
+    let mut points = std::iter::once(start).chain(samples).chain([end]);
+    let mut previous = points.next().unwrap();
+    let mut direction: Option<Vec2> = None;
+    for point in points {
+        let next = (point - previous).normalize_or_zero();
+        if let Some(direction) = direction.filter(|_| next != Vec2::ZERO) {
+            if direction.angle_between(next).abs() > Sampler::MAX_TURN {
+                return false;
+            }
+        }
+        if next != Vec2::ZERO {
+            direction = Some(next);
+        }
+        previous = point;
+    }
+
+    true
+}
+
+pub fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
     }
 }
 
@@ -80,10 +603,10 @@ pub(super) fn plugin(app: &mut App) {
         .add_systems(
             FixedUpdate,
             (
+                apply_teeth,
                 rotate_gears,
                 update_pen_pos,
                 update_line,
-                draw_line,
                 draw_gizmos,
             )
                 .chain(),
@@ -94,8 +617,7 @@ pub(super) fn plugin(app: &mut App) {
 #[derive(Bundle)]
 pub struct FixedGearBundle {
     pub gear: Gear,
-    // TODO: Different fixed shapes
-    pub radius: Radius,
+    pub shape: Shape,
     pub gear_color: GearColor,
     pub transform_bundle: TransformBundle,
     pub fixed: Fixed,
@@ -108,7 +630,7 @@ impl Default for FixedGearBundle {
             gear: Gear,
             fixed: Fixed,
             draggable: Draggable,
-            radius: Radius(150.0),
+            shape: Shape::default(),
             gear_color: GearColor(color::AMBER_600),
             transform_bundle: TransformBundle::default(),
         }
@@ -116,24 +638,13 @@ impl Default for FixedGearBundle {
 }
 
 impl FixedGearBundle {
-    pub fn rand(bounds: Vec2) -> Self {
-        let mut rng = rand::thread_rng();
-
-        let half_extents = 0.5 * bounds;
-        let translation = Vec3::new(
-            rng.gen_range(-half_extents.x..half_extents.x),
-            rng.gen_range(-half_extents.y..half_extents.y),
-            0.0,
-        );
-        let radius = rng.gen_range(1.0..256.0);
+    /// A ring at the origin
+    pub fn rand(rng: &mut impl Rng, ranges: &RandomRanges) -> Self {
+        let radius = pick(rng, &ranges.fixed_radius);
         let gear_color = RAINBOW[rng.gen_range(0..RAINBOW.len())];
 
         Self {
-            transform_bundle: TransformBundle {
-                local: Transform::from_translation(translation),
-                ..default()
-            },
-            radius: Radius(radius),
+            shape: Shape::Ring { radius },
             gear_color: GearColor(gear_color),
             ..default()
         }
@@ -148,166 +659,325 @@ pub struct RotatingGearBundle {
     pub transform_bundle: TransformBundle,
     pub rotation: Rotation,
     pub speed: Speed,
-    pub pen: Pen,
-    pub pen_pos: PenPos,
-    pub line: Line,
-    pub line_color: LineColor,
+    pub side: Side,
+    pub draggable: Draggable,
 }
 
 impl Default for RotatingGearBundle {
     fn default() -> Self {
         Self {
             gear: Gear,
+            draggable: Draggable,
             rotation: Rotation(0.0),
             speed: Speed(8.0),
             radius: Radius(55.0),
-            pen: Pen(40.0),
-            pen_pos: PenPos(Vec2::ZERO),
             gear_color: GearColor(color::PURPLE_600),
-            line: Line(Vec::new()),
-            line_color: LineColor(Srgba::BLACK),
+            side: Side::Inside,
             transform_bundle: TransformBundle::default(),
         }
     }
 }
 
 impl RotatingGearBundle {
-    pub fn rand() -> Self {
-        let mut rng = rand::thread_rng();
-
+    pub fn rand(rng: &mut impl Rng, ranges: &RandomRanges) -> Self {
         let rotation = rng.gen_range(0.0..TAU);
-        let speed = rng.gen_range(0.1..16.0);
-        let radius = rng.gen_range(1.0..128.0);
+        let speed = pick(rng, &ranges.speed);
+        let radius = pick(rng, &ranges.radius);
         let gear_color = RAINBOW[rng.gen_range(0..RAINBOW.len())];
-        let pen_dis = rng.gen_range(0.0..64.0);
 
         Self {
             rotation: Rotation(rotation),
             speed: Speed(speed),
             radius: Radius(radius),
             gear_color: GearColor(gear_color),
-            pen: Pen(pen_dis),
+            ..default()
+        }
+    }
+}
+
+/// A pen carried by a rotating gear, spawned as a child of it
+#[derive(Bundle)]
+pub struct PenBundle {
+    pub pen: Pen,
+    pub pen_angle: PenAngle,
+    pub pen_width: PenWidth,
+    pub pen_style: PenStyle,
+    pub pen_pos: PenPos,
+    pub line: Line,
+    pub line_color: LineColor,
+    pub sampler: Sampler,
+}
+
+impl Default for PenBundle {
+    fn default() -> Self {
+        Self {
+            pen: Pen(40.0),
+            pen_angle: PenAngle(0.0),
+            pen_width: PenWidth(1.0),
+            pen_style: PenStyle::default(),
+            pen_pos: PenPos(Vec2::ZERO),
+            line: Line(Vec::new()),
+            line_color: LineColor(Srgba::BLACK),
+            sampler: Sampler::default(),
+        }
+    }
+}
+
+impl PenBundle {
+    pub fn rand(rng: &mut impl Rng, ranges: &RandomRanges) -> Self {
+        Self {
+            pen: Pen(pick(rng, &ranges.pen)),
             ..default()
         }
     }
 }
 
 fn setup(mut commands: Commands) {
+    // Open the scene shared through the URL on the web
+    #[cfg(target_arch = "wasm32")]
+    if let Some(snapshot) = crate::share::read() {
+        snapshot.spawn(&mut commands);
+        return;
+    }
+
     commands.insert_resource(Settings::default());
 
     commands
         .spawn(FixedGearBundle::default())
         .with_children(|parent| {
-            parent.spawn(RotatingGearBundle::default());
+            parent
+                .spawn(RotatingGearBundle::default())
+                .with_children(|gear| {
+                    gear.spawn(PenBundle::default());
+                });
         });
 }
 
 fn update_pen_pos(
     mut gizmos: Gizmos,
-    mut rotating: Query<(&mut PenPos, &Transform, &Pen), With<Rotation>>,
+    q_rotating: Query<&Transform, With<Rotation>>,
+    mut q_pens: Query<(&mut PenPos, &Pen, &PenAngle, &Parent)>,
     settings: Res<Settings>,
 ) {
-    for (mut pen_pos, rotating_transform, &Pen(pen_dist)) in rotating.iter_mut() {
-        if settings.gizmos_enabled {
+    if settings.gizmos_enabled {
+        for rotating_transform in &q_rotating {
             gizmos.axes_2d(*rotating_transform, 10.0);
         }
+    }
+
+    for (mut pen_pos, pen, &pen_angle, parent) in q_pens.iter_mut() {
+        let rotating_transform = c!(q_rotating.get(parent.get()));
 
         // Calculate pen location
         let angle = rotating_transform.rotation.to_euler(EulerRot::XYZ).2;
         pen_pos.0 = rotating_transform.translation.xy()
-            + Vec2::from_angle(angle + 90.0_f32.to_radians()) * pen_dist;
+            + Vec2::from_angle(angle).rotate(pen.offset(pen_angle));
     }
 }
 
 fn draw_gizmos(
     mut gizmos: Gizmos,
-    q_gears: Query<(&Transform, &Radius, Option<&PenPos>, &GearColor), With<Gear>>,
+    q_fixed: Query<(&Transform, &Shape, &GearColor), With<Fixed>>,
+    q_rotating: Query<(&Transform, &Radius, &GearColor), With<Rotation>>,
+    q_pens: Query<&PenPos>,
     settings: Res<Settings>,
 ) {
-    for (transform, Radius(radius), pen_pos, GearColor(color)) in &q_gears {
-        if settings.gizmos_enabled {
-            gizmos.circle_2d(transform.translation.xy(), *radius, *color);
-            gizmos.circle_2d(transform.translation.xy(), 0.1, color::RED_600);
+    if !settings.gizmos_enabled {
+        return;
+    }
 
-            if let Some(&PenPos(pos)) = pen_pos {
-                gizmos.circle_2d(pos, 1.0, color::PINK_600);
-            }
-        }
+    for (transform, shape, GearColor(color)) in &q_fixed {
+        let center = transform.translation.xy();
+
+        gizmos.linestrip_2d(
+            shape.outline().polyline().into_iter().map(|p| center + p),
+            *color,
+        );
+        gizmos.circle_2d(center, 0.1, color::RED_600);
     }
-}
 
-fn update_line(mut q_pen_pos: Query<(&mut Line, &PenPos), Without<Paused>>) {
-    for (mut line, &PenPos(pen_pos)) in q_pen_pos.iter_mut() {
-        line.0.push(pen_pos);
+    for (transform, Radius(radius), GearColor(color)) in &q_rotating {
+        gizmos.circle_2d(transform.translation.xy(), *radius, *color);
+        gizmos.circle_2d(transform.translation.xy(), 0.1, color::RED_600);
     }
-}
 
-fn draw_line(mut gizmos: Gizmos, rotating: Query<(&Line, &LineColor)>) {
-    for (line, &LineColor(line_color)) in &rotating {
-        if line_color == Srgba::BLACK {
-            gizmos.linestrip_gradient_2d(
-                line.0.iter().copied().zip(
-                    RAINBOW
-                        .iter()
-                        .copied()
-                        .flat_map(|n| std::iter::repeat(n).take(4))
-                        .cycle(),
-                ),
-            );
-        } else {
-            gizmos.linestrip_2d(line.0.iter().copied(), line_color);
-        }
+    for &PenPos(pos) in &q_pens {
+        gizmos.circle_2d(pos, 1.0, color::PINK_600);
     }
 }
 
-// Calculate the new angle and center position of the rotating circle
-fn new_angle_and_center(
-    rotation_radians: f32,
-    fixed_radius: f32,
-    rotating_radius: f32,
-) -> (f32, Vec2) {
-    // Calculate the distance traveled by the center of the small circle
-    let distance_traveled = rotation_radians * rotating_radius;
+fn update_line(
+    mut q_pens: Query<(&mut Line, &mut Sampler, &Pen, &PenAngle, &Parent), Without<PenUp>>,
+    q_gears: Query<(&Rotation, &Speed, &Radius, &Side, Option<&Paused>, &Parent)>,
+    q_fixed: Query<(&Transform, &Shape), With<Fixed>>,
+    q_teeth: Query<&Teeth>,
+    settings: Res<Settings>,
+) {
+    for (mut line, mut sampler, pen, &pen_angle, gear) in q_pens.iter_mut() {
+        let (.., paused, parent) = c!(q_gears.get(gear.get()));
+        if paused.is_some() {
+            continue;
+        }
+
+        let train = c!(Train::new(
+            gear.get(),
+            pen.offset(pen_angle),
+            &q_gears,
+            &q_fixed
+        ));
+        let rotation = train.rotation();
+
+        if line.0.is_empty() {
+            sampler.restart(rotation, train.driver());
+        } else if sampler.driver != train.driver() {
+            sampler.follow(train.driver());
+        }
+
+        // Stop drawing once the pattern starts repeating itself, which is only known for gears with
+        // teeth rolling on a fixed gear
+        let closure = train
+            .single()
+            .zip(q_teeth.get(parent.get()).ok())
+            .zip(q_teeth.get(gear.get()).ok())
+            .and_then(|((trace, &fixed_teeth), &teeth)| {
+                Closure::new(trace, fixed_teeth, teeth).or_else(|| Closure::estimate(trace))
+            });
+        // Gears with a negative speed turn backwards, so the pattern closes in whichever direction
+        // the gear has travelled since the line started
+        let travelled = rotation - sampler.start;
+        let end = match closure {
+            Some(closure) if travelled.abs() > closure.rotation => {
+                sampler.start + closure.rotation.copysign(travelled)
+            }
+            _ => rotation,
+        };
 
-    // The angle through which the small circle rotates around the center of the large circle
-    let angle_large_circle = distance_traveled / fixed_radius;
+        sampler.extend(
+            &mut line.0,
+            |rotation| train.pen_at(rotation),
+            end,
+            settings.line_tolerance,
+        );
 
-    // Total angle in radians for the small circle (due to rotation and rolling)
-    let total_angle_small_circle = rotation_radians + angle_large_circle;
+        if end != rotation {
+            // Close the loop exactly on the first point, unless an estimated closure stopped short
+            // of it
+            if let [first, .., last] = line.0.as_mut_slice() {
+                if last.distance(*first) <= settings.line_tolerance {
+                    *last = *first;
+                }
+            }
+        }
+    }
+}
 
-    // Calculate the new position of the center of the small circle
-    let center = (fixed_radius - rotating_radius) * Vec2::from_angle(angle_large_circle);
+// Size gears with a tooth count to match it
+fn apply_teeth(
+    mut q_fixed: Query<(&mut Shape, &Teeth), With<Fixed>>,
+    mut q_rotating: Query<(&mut Radius, &Teeth), With<Rotation>>,
+    settings: Res<Settings>,
+) {
+    for (mut shape, &teeth) in q_fixed.iter_mut() {
+        let perimeter = shape.outline().perimeter();
+        let factor = settings.teeth_perimeter(teeth) / perimeter;
+        if perimeter > 0.0 && (factor - 1.0).abs() > 1e-4 {
+            *shape = shape.scaled(factor);
+        }
+    }
 
-    (total_angle_small_circle, center)
+    for (mut radius, &teeth) in q_rotating.iter_mut() {
+        radius.0 = settings.teeth_radius(teeth);
+    }
 }
 
 fn rotate_gears(
-    q_fixed: Query<(&Transform, &Radius, &Children), (With<Fixed>, Without<Rotation>)>,
+    q_fixed: Query<(&Transform, &Shape, &Children), (With<Fixed>, Without<Rotation>)>,
     mut q_gears: Query<
-        (&mut Transform, &mut Rotation, &Speed, &Radius),
-        (With<Rotation>, Without<Fixed>, Without<Paused>),
+        (
+            &mut Transform,
+            &mut Rotation,
+            &Speed,
+            &Radius,
+            &Side,
+            Option<&Paused>,
+            Option<&Children>,
+        ),
+        (With<Rotation>, Without<Fixed>),
     >,
     time: Res<Time>,
 ) {
-    for (fixed_transform, &Radius(fixed_radius), children) in &q_fixed {
-        for &child in children.iter() {
-            if let Ok((
-                mut rotating_transform,
-                mut rotation,
-                Speed(speed),
-                &Radius(rotating_radius),
-            )) = q_gears.get_mut(child)
-            {
-                // Move the rotating gear around the fixed gear
-                rotation.0 += speed * time.delta().as_secs_f32();
+    let delta = time.delta().as_secs_f32();
+    for (fixed_transform, shape, children) in &q_fixed {
+        roll(
+            &mut q_gears,
+            children,
+            shape,
+            fixed_transform.translation.xy(),
+            0.0,
+            delta,
+        );
+    }
+}
 
-                // Based on the rotation, calculate the new position and the new angle of the rotating gea,
-                let (angle, new_pos) =
-                    new_angle_and_center(rotation.0, fixed_radius, rotating_radius);
+/// Turn the rotating gears `children` along `shape`, which is at `center` turned by `angle`, then
+/// the gears rolling on them in turn
+fn roll(
+    q_gears: &mut Query<
+        (
+            &mut Transform,
+            &mut Rotation,
+            &Speed,
+            &Radius,
+            &Side,
+            Option<&Paused>,
+            Option<&Children>,
+        ),
+        (With<Rotation>, Without<Fixed>),
+    >,
+    children: &[Entity],
+    shape: &Shape,
+    center: Vec2,
+    angle: f32,
+    delta: f32,
+) {
+    for &child in children {
+        let Ok((
+            mut transform,
+            mut rotation,
+            Speed(speed),
+            &Radius(radius),
+            &side,
+            paused,
+            grandchildren,
+        )) = q_gears.get_mut(child)
+        else {
+            continue;
+        };
 
-                rotating_transform.translation = fixed_transform.translation + new_pos.extend(0.0);
-                rotating_transform.rotation = Quat::from_rotation_z(-angle);
-            }
+        // Paused gears stay put on the gear they roll on, but still follow it around
+        if paused.is_none() {
+            rotation.0 += speed * delta;
+        }
+
+        // Based on the rotation, calculate the new position and the new angle of the rotating gear
+        let (local_angle, local_center) =
+            Trace::new(shape, radius, side).angle_and_center(rotation.0);
+        let gear_center = center + Vec2::from_angle(angle).rotate(local_center);
+        let gear_angle = angle + local_angle;
+
+        transform.translation = gear_center.extend(transform.translation.z);
+        transform.rotation = Quat::from_rotation_z(gear_angle);
+
+        let grandchildren = grandchildren.map(|children| children.to_vec());
+        if let Some(grandchildren) = grandchildren {
+            roll(
+                q_gears,
+                &grandchildren,
+                &Shape::Ring { radius },
+                gear_center,
+                gear_angle,
+                delta,
+            );
         }
     }
 }
@@ -315,15 +985,21 @@ fn rotate_gears(
 fn drag_start(
     trigger: Trigger<DragStart>,
     mut commands: Commands,
-    q_fixed: Query<&Children, With<Fixed>>,
+    q_children: Query<&Children>,
     q_rotating: Query<Entity, With<Rotation>>,
+    q_pens: Query<&Parent, With<Pen>>,
 ) {
-    for children in q_fixed.get(trigger.entity()).iter() {
-        for &child in children.iter() {
-            if let Ok(entity) = q_rotating.get(child) {
-                commands.entity(entity).insert(Paused);
-            }
-        }
+    // Hold the gear still while one of its pens is moved around on it
+    if let Ok(gear) = q_pens.get(trigger.entity()) {
+        commands.entity(gear.get()).insert(Paused);
+        return;
+    }
+
+    // Dragged rotating gears stop too, along with the gears rolling on them
+    let entities =
+        std::iter::once(trigger.entity()).chain(q_children.iter_descendants(trigger.entity()));
+    for entity in q_rotating.iter_many(entities) {
+        commands.entity(entity).insert(Paused);
     }
 }
 
@@ -331,13 +1007,30 @@ fn drag_end(
     trigger: Trigger<DragEnd>,
     mut commands: Commands,
     mut q_fixed: Query<(Entity, &mut Transform, &Children), With<Fixed>>,
+    q_children: Query<&Children>,
     q_rotating: Query<Entity, With<Rotation>>,
+    mut q_pens: Query<(&mut Line, &Parent), With<Pen>>,
 ) {
+    // A moved pen starts a new curve rather than joining up with the old one
+    if let Ok((mut line, gear)) = q_pens.get_mut(trigger.entity()) {
+        line.0 = Vec::new();
+        commands.entity(gear.get()).remove::<Paused>();
+        return;
+    }
+
+    let entities =
+        std::iter::once(trigger.entity()).chain(q_children.iter_descendants(trigger.entity()));
+    for entity in q_rotating.iter_many(entities) {
+        commands.entity(entity).remove::<Paused>();
+    }
+
     // A drag just finished, snap!
     const SNAP_DIST: f32 = 10.0;
 
-    // Find the transform of the given entity
-    let (_entity, t1, _children) = r!(q_fixed.get(trigger.entity()));
+    // Find the transform of the given entity, rotating gears stay where they roll
+    let Ok((_entity, t1, _children)) = q_fixed.get(trigger.entity()) else {
+        return;
+    };
 
     // Find the closest fixed transform less than the SNAP_DIST
     let mut min_dist = f32::INFINITY;
@@ -354,12 +1047,142 @@ fn drag_end(
         let (_entity, mut t1, _children) = r!(q_fixed.get_mut(trigger.entity()));
         t1.translation = translation;
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bevy::ecs::system::RunSystemOnce;
+
+    /// A wheel with `wheel` teeth rolling inside a ring with `ring` teeth
+    fn kit(ring: u32, wheel: u32) -> Trace {
+        let settings = Settings::default();
+        let ring = Shape::Ring {
+            radius: settings.teeth_radius(Teeth(ring)),
+        };
+        Trace::new(&ring, settings.teeth_radius(Teeth(wheel)), Side::Inside)
+    }
+
+    #[test]
+    fn closure_divides_teeth_by_their_gcd() {
+        let trace = kit(96, 36);
+        let closure = Closure::new(&trace, Teeth(96), Teeth(36)).unwrap();
+
+        assert_eq!(closure.lobes, 8);
+        assert_eq!(closure.laps, 3);
+        assert!((closure.rotation - 3.0 * trace.rotation_per_lap().unwrap()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn closure_of_coprime_teeth_takes_every_lap() {
+        let closure = Closure::new(&kit(105, 32), Teeth(105), Teeth(32)).unwrap();
+
+        assert_eq!(closure.lobes, 105);
+        assert_eq!(closure.laps, 32);
+    }
+
+    #[test]
+    fn closure_needs_teeth() {
+        let trace = kit(96, 36);
+
+        assert!(Closure::new(&trace, Teeth(0), Teeth(36)).is_none());
+        assert!(Closure::new(&trace, Teeth(96), Teeth(0)).is_none());
+    }
 
-    for (_entity, _transform, children) in q_fixed.get(trigger.entity()).iter() {
-        for &child in children.iter() {
-            if let Ok(entity) = q_rotating.get(child) {
-                commands.entity(entity).remove::<Paused>();
+    #[test]
+    fn closure_of_corners_cut_by_the_wheel_is_estimated() {
+        let settings = Settings::default();
+        let wheel = settings.teeth_radius(Teeth(24));
+        let triangle = |corner| Shape::Triangle {
+            radius: settings.teeth_radius(Teeth(96)),
+            corner,
+        };
+
+        // Rolling inside sharp corners cuts them, so the path is shorter than the teeth say
+        let sharp = Trace::new(&triangle(0.0), wheel, Side::Inside);
+        assert!(Closure::new(&sharp, Teeth(96), Teeth(24)).is_none());
+        assert!(Closure::estimate(&sharp).is_some());
+
+        // Corners rounder than the wheel, and the outside of any corner, are followed all the way
+        let round = Trace::new(&triangle(wheel), wheel, Side::Inside);
+        assert!(Closure::new(&round, Teeth(96), Teeth(24)).is_some());
+        let outside = Trace::new(&triangle(0.0), wheel, Side::Outside);
+        assert!(Closure::new(&outside, Teeth(96), Teeth(24)).is_some());
+    }
+
+    #[test]
+    fn estimated_closure_matches_teeth() {
+        for (ring, wheel) in [(96, 24), (96, 36), (105, 42), (144, 60)] {
+            let trace = kit(ring, wheel);
+            let exact = Closure::new(&trace, Teeth(ring), Teeth(wheel)).unwrap();
+            let estimate = Closure::estimate(&trace).unwrap();
+
+            assert_eq!(estimate.lobes, exact.lobes, "{ring}:{wheel}");
+            assert_eq!(estimate.laps, exact.laps, "{ring}:{wheel}");
+        }
+    }
+
+    #[test]
+    fn rotation_at_undoes_angle_and_center() {
+        let shapes = [
+            Shape::Ring { radius: 150.0 },
+            Shape::Bar {
+                size: Vec2::new(300.0, 150.0),
+                corner: 40.0,
+            },
+        ];
+
+        for shape in shapes {
+            for side in [Side::Inside, Side::Outside] {
+                let trace = Trace::new(&shape, 30.0, side);
+                let lap = trace.rotation_per_lap().unwrap();
+                for i in 0..16 {
+                    // Past the first lap, the rotation is found again on the lap it is near
+                    let rotation = 1.5 * lap * i as f32 / 16.0;
+                    let (_angle, center) = trace.angle_and_center(rotation);
+                    let found = trace.rotation_at(center, rotation + 0.1);
+                    assert!((found - rotation).abs() < 1e-2, "{} != {}", found, rotation);
+                }
             }
         }
     }
+
+    #[test]
+    fn pen_on_a_stopped_gear_is_traced_while_its_carrier_turns() {
+        let mut world = World::new();
+        world.insert_resource(Settings::default());
+        let mut carrier = None;
+        let mut pen = None;
+        world
+            .spawn((Fixed, Transform::default(), Shape::Ring { radius: 150.0 }))
+            .with_children(|parent| {
+                let mut entity =
+                    parent.spawn((Rotation(0.0), Speed(1.0), Radius(60.0), Side::Inside));
+                entity.with_children(|parent| {
+                    parent
+                        .spawn((Rotation(0.0), Speed(0.0), Radius(20.0), Side::Inside))
+                        .with_children(|parent| {
+                            let entity = parent.spawn((
+                                Pen(10.0),
+                                PenAngle(0.0),
+                                Line(Vec::new()),
+                                Sampler::default(),
+                            ));
+                            pen = Some(entity.id());
+                        });
+                });
+                carrier = Some(entity.id());
+            });
+        let (carrier, pen) = (carrier.unwrap(), pen.unwrap());
+
+        world.run_system_once(update_line);
+        for _ in 0..4 {
+            world.get_mut::<Rotation>(carrier).unwrap().0 += 0.5;
+            world.run_system_once(update_line);
+        }
+
+        let line = &world.get::<Line>(pen).unwrap().0;
+        let (first, last) = (line[0], line[line.len() - 1]);
+        assert!(line.len() > 2 && first.distance(last) > 1.0, "{:?}", line);
+    }
 }
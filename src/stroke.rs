@@ -0,0 +1,294 @@
+use crate::{
+    prelude::*,
+    spiro::{Line, LineColor, PenStyle, PenWidth},
+};
+use bevy::{
+    render::{mesh::PrimitiveTopology, primitives::Aabb, render_asset::RenderAssetUsages},
+    sprite::{MaterialMesh2dBundle, Mesh2dHandle},
+};
+use serde::{Deserialize, Serialize};
+
+/// Points per mesh, only the meshes holding the end of a line are rebuilt as it grows
+const CHUNK_POINTS: usize = 1024;
+
+/// Longest miter relative to the line width before falling back to a bevel
+const MITER_LIMIT: f32 = 4.0;
+
+/// Largest angle between the steps of round caps and joins
+const ROUND_STEP: f32 = std::f32::consts::PI / 8.0;
+
+/// How the ends of a line are drawn
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, Serialize, Deserialize)]
+pub enum Cap {
+    /// Stop flat at the end point
+    Butt,
+    /// Stop flat half the width past the end point
+    Square,
+    #[default]
+    Round,
+}
+
+/// How the corners of a line are drawn
+#[derive(Clone, Copy, PartialEq, Eq, Default, Debug, Serialize, Deserialize)]
+pub enum Join {
+    /// Extend the edges until they meet, cut short past [`MITER_LIMIT`]
+    Miter,
+    /// Cut the corner off flat
+    Bevel,
+    #[default]
+    Round,
+}
+
+pub(super) fn plugin(app: &mut App) {
+    app //
+        .add_systems(Startup, setup)
+        .add_systems(
+            Update,
+            (track_lines, update_meshes, despawn_orphaned_chunks).chain(),
+        );
+}
+
+#[derive(Resource)]
+struct LineMaterial(Handle<ColorMaterial>);
+
+/// What has already been turned into meshes for a [`Line`]
+#[derive(Component, Default)]
+struct LineMesh {
+    chunks: Vec<(Entity, Handle<Mesh>)>,
+    /// Number of points in the meshes
+    points: usize,
+    /// The last two points in the meshes, to notice when the line was edited rather than extended
+    tail: [Vec2; 2],
+    style: Option<(Srgba, PenWidth, PenStyle)>,
+}
+
+/// A mesh holding part of the line of `owner`
+#[derive(Component)]
+struct LineChunk {
+    owner: Entity,
+}
+
+fn setup(mut commands: Commands, mut materials: ResMut<Assets<ColorMaterial>>) {
+    // Vertex colors are multiplied by the material color
+    commands.insert_resource(LineMaterial(materials.add(Color::WHITE)));
+}
+
+fn track_lines(mut commands: Commands, q_lines: Query<Entity, (With<Line>, Without<LineMesh>)>) {
+    for entity in &q_lines {
+        commands.entity(entity).insert(LineMesh::default());
+    }
+}
+
+fn update_meshes(
+    mut commands: Commands,
+    mut q_lines: Query<(
+        Entity,
+        &Line,
+        &LineColor,
+        &PenWidth,
+        &PenStyle,
+        &mut LineMesh,
+    )>,
+    mut meshes: ResMut<Assets<Mesh>>,
+    material: Res<LineMaterial>,
+) {
+    for (owner, Line(points), line_color, &pen_width, &pen_style, mut line_mesh) in
+        q_lines.iter_mut()
+    {
+        let uploaded = line_mesh.points;
+        let style = (line_color.0, pen_width, pen_style);
+
+        // Find the first point that needs new vertices, a moved point also changes the join of the
+        // point before it
+        let dirty = if line_mesh.style != Some(style)
+            || uploaded < 2
+            || points.len() < uploaded
+            || points[uploaded - 2] != line_mesh.tail[0]
+        {
+            0
+        } else if points.len() == uploaded && points[uploaded - 1] == line_mesh.tail[1] {
+            continue;
+        } else {
+            uploaded - 2
+        };
+
+        let chunks = if points.len() < 2 {
+            0
+        } else {
+            (points.len() - 2) / (CHUNK_POINTS - 1) + 1
+        };
+
+        // Drop meshes past the end of the line
+        if line_mesh.chunks.len() > chunks {
+            for (entity, _mesh) in line_mesh.chunks.drain(chunks..) {
+                commands.entity(entity).despawn();
+            }
+        }
+
+        // Rebuild the chunks holding dirty points, including the one ending on the point before
+        let first_chunk = dirty.saturating_sub(1) / (CHUNK_POINTS - 1);
+        for chunk in first_chunk..chunks {
+            let start = chunk * (CHUNK_POINTS - 1);
+            let end = (start + CHUNK_POINTS).min(points.len());
+            let mesh = chunk_mesh(points, start..end, line_color, pen_width, pen_style);
+
+            match line_mesh.chunks.get(chunk) {
+                Some((entity, handle)) => {
+                    meshes.insert(handle, mesh);
+                    // Bounds are only computed for entities without them, drop the stale ones so
+                    // the grown chunk isn't culled by the bounds of its first points
+                    commands.entity(*entity).remove::<Aabb>();
+                }
+                None => {
+                    let handle = meshes.add(mesh);
+                    let entity = commands
+                        .spawn((
+                            MaterialMesh2dBundle {
+                                mesh: Mesh2dHandle(handle.clone()),
+                                material: material.0.clone(),
+                                ..default()
+                            },
+                            LineChunk { owner },
+                        ))
+                        .id();
+                    line_mesh.chunks.push((entity, handle));
+                }
+            }
+        }
+
+        line_mesh.points = points.len();
+        line_mesh.style = Some(style);
+        if let [.., a, b] = points.as_slice() {
+            line_mesh.tail = [*a, *b];
+        }
+    }
+}
+
+fn despawn_orphaned_chunks(
+    mut commands: Commands,
+    q_chunks: Query<(Entity, &LineChunk)>,
+    q_lines: Query<(), With<LineMesh>>,
+) {
+    for (entity, chunk) in &q_chunks {
+        if !q_lines.contains(chunk.owner) {
+            commands.entity(entity).despawn();
+        }
+    }
+}
+
+/// Triangle strip covering `range` of the line, joined to the points around it
+fn chunk_mesh(
+    points: &[Vec2],
+    range: std::ops::Range<usize>,
+    line_color: &LineColor,
+    PenWidth(width): PenWidth,
+    style: PenStyle,
+) -> Mesh {
+    let mut positions = Vec::with_capacity(2 * range.len());
+    let mut colors = Vec::with_capacity(2 * range.len());
+
+    for i in range {
+        let color = LinearRgba::from(line_color.at(i)).to_f32_array();
+        for [left, right] in edge_pairs(points, i, 0.5 * width, style) {
+            positions.extend([left.extend(0.0), right.extend(0.0)]);
+            colors.extend([color, color]);
+        }
+    }
+
+    Mesh::new(
+        PrimitiveTopology::TriangleStrip,
+        RenderAssetUsages::RENDER_WORLD,
+    )
+    .with_inserted_attribute(Mesh::ATTRIBUTE_POSITION, positions)
+    .with_inserted_attribute(Mesh::ATTRIBUTE_COLOR, colors)
+}
+
+/// Left and right edge points of the stroke around the `index`th point of `points`.
+///
+/// Consecutive pairs, including those of neighboring points, form the quads of a triangle strip
+/// covering the line with its caps and joins.
+pub fn edge_pairs(
+    points: &[Vec2],
+    index: usize,
+    half_width: f32,
+    style: PenStyle,
+) -> Vec<[Vec2; 2]> {
+    let point = points[index];
+    let incoming = index
+        .checked_sub(1)
+        .map_or(Vec2::ZERO, |i| (point - points[i]).normalize_or_zero());
+    let outgoing = points
+        .get(index + 1)
+        .map_or(Vec2::ZERO, |&p| (p - point).normalize_or_zero());
+
+    let pair = |normal: Vec2| [point + half_width * normal, point - half_width * normal];
+
+    // Caps are built looking out of the line, flip them around for its start
+    let start_cap = |direction: Vec2| {
+        cap(point, -direction, half_width, style.cap)
+            .into_iter()
+            .rev()
+            .map(|[left, right]| [right, left])
+    };
+    let end_cap = |direction: Vec2| cap(point, direction, half_width, style.cap);
+
+    match (incoming != Vec2::ZERO, outgoing != Vec2::ZERO) {
+        (false, true) => start_cap(outgoing).collect(),
+        (true, false) => end_cap(incoming),
+        // A lone point is both the start and the end of the line
+        (false, false) => start_cap(Vec2::X).chain(end_cap(Vec2::X)).collect(),
+        (true, true) => {
+            let normal_in = incoming.perp();
+            let normal_out = outgoing.perp();
+            let turn = incoming.angle_between(outgoing);
+
+            match style.join {
+                Join::Miter => {
+                    // Stretch the offset so both segments keep their width
+                    let direction = (normal_in + normal_out).normalize_or_zero();
+                    let scale = 1.0 / direction.dot(normal_in);
+                    if direction != Vec2::ZERO && scale <= MITER_LIMIT {
+                        vec![pair(scale * direction)]
+                    } else {
+                        vec![pair(normal_in), pair(normal_out)]
+                    }
+                }
+                Join::Bevel => vec![pair(normal_in), pair(normal_out)],
+                Join::Round => {
+                    let steps = (turn.abs() / ROUND_STEP).ceil().max(1.0) as usize;
+                    (0..=steps)
+                        .map(|i| {
+                            pair(Vec2::from_angle(turn * i as f32 / steps as f32).rotate(normal_in))
+                        })
+                        .collect()
+                }
+            }
+        }
+    }
+}
+
+/// Edge pairs capping a line ending at `point` heading in `direction`, ordered towards the tip
+fn cap(point: Vec2, direction: Vec2, half_width: f32, cap: Cap) -> Vec<[Vec2; 2]> {
+    let normal = direction.perp();
+
+    match cap {
+        Cap::Butt => vec![[point + half_width * normal, point - half_width * normal]],
+        Cap::Square => {
+            let end = point + half_width * direction;
+            vec![[end + half_width * normal, end - half_width * normal]]
+        }
+        Cap::Round => {
+            let steps = (0.5 * std::f32::consts::PI / ROUND_STEP).ceil() as usize;
+            (0..=steps)
+                .rev()
+                .map(|i| {
+                    // From the full width down to the tip of the semicircle
+                    let angle = 0.5 * std::f32::consts::PI * i as f32 / steps as f32;
+                    let along = half_width * angle.cos() * direction;
+                    let across = half_width * angle.sin() * normal;
+                    [point + along + across, point + along - across]
+                })
+                .collect()
+        }
+    }
+}
@@ -1,22 +1,71 @@
 use crate::{
+    camera::{FitAll, ResetView},
+    export::{Export, ExportReport, ExportSettings, GcodeSettings, HpglSettings, Page},
+    history::{History, Redo, Remove, Undo},
     prelude::*,
+    preset::{Kit, Presets, RemovePreset, SavePreset, PATTERNS, RING_TEETH, WHEEL_TEETH},
+    random::{Random, RandomMode, Seed},
+    scene::{OpenScene, SaveScene, SceneOptions},
+    shape::{simplify, Shape},
     spiro::{
-        Fixed, FixedGearBundle, GearColor, Line, LineColor, Paused, Pen, Radius,
-        RotatingGearBundle, Rotation, Settings, Speed,
+        Closure, Fixed, GearColor, Line, LineColor, Paused, Pen, PenAngle, PenBundle, PenStyle,
+        PenUp, PenWidth, Radius, Rotation, Sampler, Settings, Side, Speed, Teeth, Trace,
     },
+    stroke::{Cap, Join},
 };
 use bevy::window::PrimaryWindow;
 use bevy_egui::{
     egui::{
-        self, Button, CollapsingHeader, Color32, CursorIcon, DragValue, Frame, Grid, ScrollArea,
-        SidePanel, Ui,
+        self, Button, CollapsingHeader, Color32, ComboBox, CursorIcon, DragValue, Frame, Grid,
+        ScrollArea, SidePanel, Ui,
     },
     EguiContexts,
 };
+use std::{
+    f32::consts::TAU,
+    ops::{Range, RangeInclusive},
+};
+
+/// Most points in a curve rendered at once, long patterns get fewer points per turn instead
+const RENDER_POINTS: f32 = 1_000_000.0;
 
 #[derive(Resource)]
 pub struct Cursor(pub Option<CursorIcon>);
 
+type RotatingGears<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static Rotation,
+        &'static mut GearColor,
+        &'static mut Speed,
+        &'static mut Radius,
+        &'static mut Side,
+        Option<&'static mut Teeth>,
+        Option<&'static Seed>,
+        Option<&'static Paused>,
+        Option<&'static Children>,
+    ),
+    (With<Rotation>, Without<Fixed>),
+>;
+
+type Pens<'w, 's> = Query<
+    'w,
+    's,
+    (
+        Entity,
+        &'static mut Pen,
+        &'static mut PenAngle,
+        &'static mut PenWidth,
+        &'static mut PenStyle,
+        &'static mut LineColor,
+        &'static mut Line,
+        &'static mut Sampler,
+        Option<&'static PenUp>,
+    ),
+>;
+
 pub(super) fn plugin(app: &mut App) {
     app //
         .insert_resource(Cursor(None))
@@ -29,36 +78,51 @@ fn input(mut settings: ResMut<Settings>, keys: Res<ButtonInput<KeyCode>>) {
     }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn ui(
     mut commands: Commands,
     mut contexts: EguiContexts,
     mut q_fixed: Query<
-        (Entity, &mut Radius, &mut GearColor, &Children),
-        (With<Fixed>, Without<Rotation>),
-    >,
-    mut q_rotating: Query<
         (
             Entity,
-            &mut Line,
-            &mut LineColor,
+            &Transform,
+            &mut Shape,
             &mut GearColor,
-            &mut Speed,
-            &mut Pen,
-            &mut Radius,
-            Option<&Paused>,
+            Option<&mut Teeth>,
+            Option<&Seed>,
+            &Children,
         ),
-        (With<Rotation>, Without<Fixed>),
+        (With<Fixed>, Without<Rotation>),
     >,
+    mut q_rotating: RotatingGears,
+    mut q_pens: Pens,
     q_primary_window: Query<&Window, With<PrimaryWindow>>,
+    q_camera: Query<(&Transform, &OrthographicProjection), With<Camera>>,
     mut settings: ResMut<Settings>,
+    mut export_settings: ResMut<ExportSettings>,
+    export_report: Res<ExportReport>,
+    mut scene_options: ResMut<SceneOptions>,
+    history: Res<History>,
+    mut random: ResMut<Random>,
+    mut presets: ResMut<Presets>,
 ) {
     SidePanel::left("SPIRO")
         .resizable(false)
         .frame(Frame::none().fill(Color32::BLACK).inner_margin(10.0))
         .show_animated(contexts.ctx_mut(), settings.show_sidebar, |ui| {
             ScrollArea::vertical().show(ui, |ui| {
-                for (i_fixed, (fixed_entity, mut radius, mut gear_color, children)) in
-                    q_fixed.iter_mut().enumerate()
+                for (
+                    i_fixed,
+                    (
+                        fixed_entity,
+                        fixed_transform,
+                        mut shape,
+                        mut gear_color,
+                        mut fixed_teeth,
+                        fixed_seed,
+                        children,
+                    ),
+                ) in q_fixed.iter_mut().enumerate()
                 {
                     CollapsingHeader::new(format!("Spirograph #{}", i_fixed + 1))
                         .default_open(true)
@@ -68,117 +132,54 @@ fn ui(
                                 .num_columns(2)
                                 .spacing([40.0, 4.0])
                                 .striped(true)
-                                .show(ui, |mut ui| {
-                                    ui.label("Radius");
-                                    ui.add(
-                                        DragValue::new(&mut radius.0).range(1.0..=128.0).speed(0.1),
+                                .show(ui, |ui| {
+                                    let perimeter = shape.outline().perimeter();
+                                    teeth_editor(
+                                        ui,
+                                        &mut commands,
+                                        fixed_entity,
+                                        fixed_teeth.as_deref_mut(),
+                                        perimeter / settings.teeth_perimeter(Teeth(1)),
                                     );
-                                    ui.end_row();
+
+                                    shape_editor(ui, i_fixed, &mut shape, fixed_teeth.is_none());
 
                                     ui.label("Gear color");
-                                    color_picker(&mut ui, &mut gear_color.0);
+                                    color_picker(ui, &mut gear_color.0);
                                     ui.end_row();
+
+                                    seed_row(ui, fixed_seed, &mut random);
                                 });
 
                             // Rotating gears
-                            for (i, child) in children.iter().enumerate() {
-                                if let Ok((
-                                    rotating_entity,
-                                    mut line,
-                                    mut line_color,
-                                    mut gear_color,
-                                    mut speed,
-                                    mut pen,
-                                    mut radius,
-                                    paused,
-                                )) = q_rotating.get_mut(*child)
-                                {
-                                    CollapsingHeader::new(format!("Gear #{}", i + 1))
-                                        .default_open(true)
-                                        .show(ui, |ui| {
-                                            // Gear settings
-                                            Grid::new(format!("grid {i_fixed} {i}"))
-                                                .num_columns(2)
-                                                .spacing([40.0, 4.0])
-                                                .striped(true)
-                                                .show(ui, |mut ui| {
-                                                    ui.label("Speed");
-                                                    ui.add(
-                                                        DragValue::new(&mut speed.0)
-                                                            .range(0.0..=128.0)
-                                                            .speed(0.1),
-                                                    );
-                                                    ui.end_row();
-
-                                                    ui.label("Radius");
-                                                    ui.add(
-                                                        DragValue::new(&mut radius.0)
-                                                            .range(0.0..=128.0)
-                                                            .speed(0.1),
-                                                    );
-                                                    ui.end_row();
-
-                                                    ui.label("Pen distance");
-                                                    ui.add(
-                                                        DragValue::new(&mut pen.0)
-                                                            .range(0.0..=128.0)
-                                                            .speed(0.1),
-                                                    );
-                                                    ui.end_row();
-
-                                                    ui.label("Line color");
-                                                    color_picker(&mut ui, &mut line_color.0);
-                                                    ui.end_row();
-
-                                                    ui.label("Gear color");
-                                                    color_picker(&mut ui, &mut gear_color.0);
-                                                    ui.end_row();
-
-                                                    ui.label("Line length");
-                                                    ui.label(format!("{}", line.0.len()));
-                                                    ui.end_row();
-                                                });
-
-                                            // Gear controls
-                                            ui.horizontal(|ui| {
-                                                if ui.add(Button::new("Clear line")).clicked() {
-                                                    line.0 = Vec::new();
-                                                }
-
-                                                if ui.add(Button::new("Remove gear")).clicked() {
-                                                    commands.entity(rotating_entity).despawn();
-                                                }
-
-                                                {
-                                                    let mut toggle = paused.is_some();
-                                                    ui.toggle_value(&mut toggle, "Pause");
-                                                    if toggle != paused.is_some() {
-                                                        if toggle {
-                                                            commands
-                                                                .entity(rotating_entity)
-                                                                .insert(Paused);
-                                                        } else {
-                                                            commands
-                                                                .entity(rotating_entity)
-                                                                .remove::<Paused>();
-                                                        }
-                                                    }
-                                                }
-                                            });
-                                        });
-                                }
+                            let track = (&*shape, fixed_teeth.as_deref().copied());
+                            for (i, &child) in children.iter().enumerate() {
+                                rotating_gear_ui(
+                                    ui,
+                                    &mut commands,
+                                    (&mut q_rotating, &mut q_pens),
+                                    &mut random,
+                                    &settings,
+                                    child,
+                                    &[i_fixed, i],
+                                    track,
+                                    Some(fixed_transform.translation.xy()),
+                                );
                             }
 
                             // Spirograph controls
                             ui.horizontal(|ui| {
                                 if ui.add(Button::new("Add gear")).clicked() {
-                                    commands.entity(fixed_entity).with_children(|parent| {
-                                        parent.spawn(RotatingGearBundle::rand());
-                                    });
+                                    random.spawn_gear(
+                                        &mut commands,
+                                        fixed_entity,
+                                        (&shape, &gear_color, fixed_teeth.as_deref()),
+                                        &settings,
+                                    );
                                 }
 
                                 if ui.add(Button::new("Remove spirograph")).clicked() {
-                                    commands.entity(fixed_entity).despawn_recursive();
+                                    commands.trigger(Remove(fixed_entity));
                                 }
                             });
                         });
@@ -187,29 +188,53 @@ fn ui(
                 ui.separator();
 
                 // Global controls
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(history.can_undo(), Button::new("Undo"))
+                        .clicked()
+                    {
+                        commands.trigger(Undo);
+                    }
+                    if ui
+                        .add_enabled(history.can_redo(), Button::new("Redo"))
+                        .clicked()
+                    {
+                        commands.trigger(Redo);
+                    }
+
+                    if ui.add(Button::new("Fit all")).clicked() {
+                        commands.trigger(FitAll);
+                    }
+                    if ui.add(Button::new("Reset view")).clicked() {
+                        commands.trigger(ResetView);
+                    }
+                });
+
                 ui.horizontal(|ui| {
                     ui.toggle_value(&mut settings.gizmos_enabled, "Enable gizmos");
 
                     if ui.add(Button::new("Clear all")).clicked() {
-                        for (_entity, mut line, ..) in q_rotating.iter_mut() {
+                        for (.., mut line, _sampler, _pen_up) in q_pens.iter_mut() {
                             line.0 = Vec::new();
                         }
                     }
 
                     if ui.add(Button::new("Add")).clicked() {
                         let primary_window = r!(q_primary_window.get_single());
+                        let (camera_transform, projection) = r!(q_camera.get_single());
 
-                        commands
-                            .spawn(FixedGearBundle::rand(primary_window.size()))
-                            .with_children(|parent| {
-                                parent.spawn(RotatingGearBundle::rand());
-                            });
+                        // Somewhere in view
+                        let view = Rect::from_center_size(
+                            camera_transform.translation.xy(),
+                            primary_window.size() * projection.scale,
+                        );
+                        random.spawn_spirograph(&mut commands, view, &settings);
                     }
 
                     if ui.add(Button::new("Pause all")).clicked() {
                         let paused = q_rotating
                             .iter()
-                            .any(|(_, _, _, _, _, _, _, paused)| paused.is_some());
+                            .any(|(.., paused, _children)| paused.is_some());
 
                         for (entity, ..) in &q_rotating {
                             if paused {
@@ -221,6 +246,201 @@ fn ui(
                     }
                 });
 
+                ui.horizontal(|ui| {
+                    ui.label("Tooth size");
+                    ui.add(
+                        DragValue::new(&mut settings.tooth_size)
+                            .range(0.5..=16.0)
+                            .speed(0.01),
+                    );
+
+                    ui.label("Render resolution");
+                    ui.add(
+                        DragValue::new(&mut settings.render_resolution)
+                            .range(8..=4096)
+                            .suffix(" / turn"),
+                    );
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Line tolerance");
+                    ui.add(
+                        DragValue::new(&mut settings.line_tolerance)
+                            .range(0.01..=8.0)
+                            .speed(0.01),
+                    );
+                });
+
+                ui.horizontal(|ui| {
+                    ui.label("Simplify tolerance");
+                    ui.add(
+                        DragValue::new(&mut settings.simplify_tolerance)
+                            .range(0.0..=8.0)
+                            .speed(0.01),
+                    );
+                });
+
+                ui.separator();
+
+                CollapsingHeader::new("Randomizer").show(ui, |ui| {
+                    Grid::new("randomizer grid")
+                        .num_columns(2)
+                        .spacing([40.0, 4.0])
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.label("Mode");
+                            ui.horizontal(|ui| {
+                                ui.selectable_value(&mut random.mode, RandomMode::Free, "Free");
+                                ui.selectable_value(
+                                    &mut random.mode,
+                                    RandomMode::Pleasing,
+                                    "Pleasing",
+                                );
+                            });
+                            ui.end_row();
+
+                            ui.label("Next seed");
+                            ui.add(DragValue::new(&mut random.seed));
+                            ui.end_row();
+
+                            let ranges = &mut random.ranges;
+                            range_editor(
+                                ui,
+                                "Fixed radius",
+                                &mut ranges.fixed_radius,
+                                0.0..=1024.0,
+                            );
+                            range_editor(ui, "Radius", &mut ranges.radius, 0.0..=512.0);
+                            range_editor(ui, "Pen distance", &mut ranges.pen, 0.0..=512.0);
+                            range_editor(ui, "Speed", &mut ranges.speed, 0.0..=128.0);
+                        });
+                });
+
+                ui.separator();
+
+                CollapsingHeader::new("Presets").show(ui, |ui| {
+                    Grid::new("kit grid")
+                        .num_columns(2)
+                        .spacing([40.0, 4.0])
+                        .striped(true)
+                        .show(ui, |ui| kit_editor(ui, &mut presets.kit));
+
+                    if ui.add(Button::new("Add from kit")).clicked() {
+                        let kit = presets.kit;
+                        let name = format!("{}:{} hole {}", kit.ring, kit.wheel, kit.hole);
+                        kit.preset(name, &settings).spawn(&mut commands);
+                    }
+
+                    ui.label("Patterns");
+                    ui.horizontal_wrapped(|ui| {
+                        for (name, kit) in PATTERNS {
+                            if ui.add(Button::new(name)).clicked() {
+                                kit.preset(name.to_string(), &settings).spawn(&mut commands);
+                            }
+                        }
+                    });
+
+                    ui.label("Saved");
+                    let mut removed = None;
+                    for (i, preset) in presets.saved.iter().enumerate() {
+                        ui.horizontal(|ui| {
+                            if ui.add(Button::new(&preset.name)).clicked() {
+                                preset.spawn(&mut commands);
+                            }
+                            if ui.add(Button::new("Remove")).clicked() {
+                                removed = Some(i);
+                            }
+                        });
+                    }
+                    if let Some(i) = removed {
+                        commands.trigger(RemovePreset(i));
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.text_edit_singleline(&mut presets.name);
+                        if ui.add(Button::new("Save gears")).clicked() {
+                            commands.trigger(SavePreset(std::mem::take(&mut presets.name)));
+                        }
+                    });
+                });
+
+                ui.separator();
+
+                CollapsingHeader::new("Scene").show(ui, |ui| {
+                    ui.checkbox(&mut scene_options.lines, "Include lines");
+
+                    ui.horizontal(|ui| {
+                        if ui.add(Button::new("Save scene")).clicked() {
+                            commands.trigger(SaveScene);
+                        }
+                        if ui.add(Button::new("Open scene")).clicked() {
+                            commands.trigger(OpenScene);
+                        }
+                    });
+                });
+
+                ui.separator();
+
+                CollapsingHeader::new("Export").show(ui, |ui| {
+                    ui.checkbox(&mut export_settings.gears, "Include gears");
+                    ui.checkbox(&mut export_settings.optimize, "Optimize plotting order");
+
+                    Grid::new("export grid")
+                        .num_columns(2)
+                        .spacing([40.0, 4.0])
+                        .striped(true)
+                        .show(ui, |ui| {
+                            ui.label("PNG width");
+                            ui.add(
+                                DragValue::new(&mut export_settings.png_width)
+                                    .range(16..=16384)
+                                    .suffix(" px"),
+                            );
+                            ui.end_row();
+
+                            ui.label("PNG resolution");
+                            ui.horizontal(|ui| {
+                                ui.add(
+                                    DragValue::new(&mut export_settings.dpi)
+                                        .range(1..=2400)
+                                        .suffix(" dpi"),
+                                );
+                                ui.label(format!(
+                                    "{:.1} in wide",
+                                    export_settings.png_width as f32 / export_settings.dpi as f32
+                                ));
+                            });
+                            ui.end_row();
+
+                            ui.label("PNG background");
+                            ui.checkbox(&mut export_settings.transparent, "Transparent");
+                            ui.end_row();
+
+                            page_editor(ui, &mut export_settings.page);
+                            gcode_editor(ui, &mut export_settings.gcode);
+                            hpgl_editor(ui, &mut export_settings.hpgl);
+                        });
+
+                    ui.horizontal(|ui| {
+                        if ui.add(Button::new("Export SVG")).clicked() {
+                            commands.trigger(Export::Svg);
+                        }
+                        if ui.add(Button::new("Export PNG")).clicked() {
+                            commands.trigger(Export::Png);
+                        }
+                        if ui.add(Button::new("Export G-code")).clicked() {
+                            commands.trigger(Export::Gcode);
+                        }
+                        if ui.add(Button::new("Export HPGL")).clicked() {
+                            commands.trigger(Export::Hpgl);
+                        }
+                    });
+
+                    if let Some((before, after)) = export_report.travel {
+                        ui.label(format!("Pen-up travel: {before:.0} mm → {after:.0} mm"));
+                    }
+                });
+
                 ui.separator();
 
                 ui.label("Hit escape to toggle sidebar");
@@ -228,6 +448,309 @@ fn ui(
         });
 }
 
+/// Sidebar section of the rotating gear `entity` rolling on the shape and teeth of `track`, with
+/// those of its pens and of the gears rolling on it nested inside. `path` holds the index of the
+/// spirograph followed by that of each gear down to this one, and `origin` is the position of the
+/// fixed gear for gears rolling directly on one.
+#[allow(clippy::too_many_arguments)]
+fn rotating_gear_ui(
+    ui: &mut Ui,
+    commands: &mut Commands,
+    (q_rotating, q_pens): (&mut RotatingGears, &mut Pens),
+    random: &mut Random,
+    settings: &Settings,
+    entity: Entity,
+    path: &[usize],
+    (track, track_teeth): (&Shape, Option<Teeth>),
+    origin: Option<Vec2>,
+) {
+    let name = path[1..]
+        .iter()
+        .map(|i| (i + 1).to_string())
+        .collect::<Vec<_>>()
+        .join(".");
+
+    CollapsingHeader::new(format!("Gear #{name}"))
+        .default_open(true)
+        .show(ui, |ui| {
+            let Ok((
+                rotating_entity,
+                rotation,
+                mut gear_color,
+                mut speed,
+                mut radius,
+                mut side,
+                mut teeth,
+                seed,
+                paused,
+                children,
+            )) = q_rotating.get_mut(entity)
+            else {
+                return;
+            };
+
+            // Children are either pens or gears rolling on this one
+            let children = children
+                .map(|children| children.to_vec())
+                .unwrap_or_default();
+            let (pens, gears): (Vec<_>, Vec<_>) = children
+                .into_iter()
+                .partition(|&child| q_pens.contains(child));
+
+            let trace = Trace::new(track, radius.0, *side);
+            // Patterns only close predictably for gears rolling on a fixed gear, and only exactly
+            // while they follow its whole shape
+            let closure =
+                origin
+                    .and(track_teeth)
+                    .zip(teeth.as_deref())
+                    .and_then(|(track_teeth, &teeth)| {
+                        Closure::new(&trace, track_teeth, teeth)
+                            .or_else(|| Closure::estimate(&trace))
+                    });
+
+            // Gear settings
+            Grid::new(format!("grid {path:?}"))
+                .num_columns(2)
+                .spacing([40.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Speed");
+                    ui.add(DragValue::new(&mut speed.0).range(0.0..=128.0).speed(0.1));
+                    ui.end_row();
+
+                    teeth_editor(
+                        ui,
+                        commands,
+                        rotating_entity,
+                        teeth.as_deref_mut(),
+                        radius.0 / settings.teeth_radius(Teeth(1)),
+                    );
+
+                    ui.label("Radius");
+                    ui.add_enabled(
+                        teeth.is_none(),
+                        DragValue::new(&mut radius.0).range(0.0..=128.0).speed(0.1),
+                    );
+                    ui.end_row();
+
+                    ui.label("Side");
+                    ui.horizontal(|ui| {
+                        ui.selectable_value(&mut *side, Side::Inside, "Inside");
+                        ui.selectable_value(&mut *side, Side::Outside, "Outside");
+                    });
+                    ui.end_row();
+
+                    ui.label("Gear color");
+                    color_picker(ui, &mut gear_color.0);
+                    ui.end_row();
+
+                    seed_row(ui, seed, random);
+
+                    if let (Some(closure), Some(Teeth(track_teeth)), Some(Teeth(teeth))) =
+                        (&closure, track_teeth, teeth.as_deref())
+                    {
+                        ui.label("Ratio");
+                        ui.label(format!("{track_teeth}:{teeth}"));
+                        ui.end_row();
+
+                        ui.label("Lobes");
+                        ui.label(format!("{}", closure.lobes));
+                        ui.end_row();
+
+                        ui.label("Closes after");
+                        ui.label(format!(
+                            "{} laps ({:.1} turns)",
+                            closure.laps,
+                            closure.rotation / TAU
+                        ));
+                        ui.end_row();
+                    }
+                });
+
+            // Pens
+            for (i, &pen_entity) in pens.iter().enumerate() {
+                pen_ui(ui, commands, q_pens, settings, pen_entity, path, i);
+            }
+
+            // Gear controls
+            ui.horizontal(|ui| {
+                // Rendering at once needs the whole pattern, which is unknown for gears rolling on
+                // other rotating gears. The gear is paused afterwards since only gears with teeth
+                // know to stop drawing over a finished pattern
+                if ui
+                    .add_enabled(origin.is_some(), Button::new("Render and pause"))
+                    .clicked()
+                {
+                    let closure = closure
+                        .as_ref()
+                        .map(|closure| closure.rotation)
+                        .or_else(|| Closure::estimate(&trace).map(|closure| closure.rotation));
+
+                    if let (Some(closure), Some(origin)) = (closure, origin) {
+                        let resolution = (RENDER_POINTS * TAU / closure)
+                            .clamp(1.0, settings.render_resolution as f32)
+                            as u32;
+
+                        for &pen_entity in &pens {
+                            let (_, pen, angle, _, _, _, mut line, mut sampler, pen_up) =
+                                c!(q_pens.get_mut(pen_entity));
+                            if pen_up.is_some() {
+                                continue;
+                            }
+
+                            line.0 = trace
+                                .curve(rotation.0, closure, resolution, pen.offset(*angle))
+                                .into_iter()
+                                .map(|p| origin + p)
+                                .collect();
+
+                            // The curve is complete, stop drawing over it
+                            *sampler = Sampler::completed(rotation.0, closure);
+                        }
+                        commands.entity(rotating_entity).insert(Paused);
+                    }
+                }
+
+                if ui.add(Button::new("Remove gear")).clicked() {
+                    commands.trigger(Remove(rotating_entity));
+                }
+
+                {
+                    let mut toggle = paused.is_some();
+                    ui.toggle_value(&mut toggle, "Pause");
+                    if toggle != paused.is_some() {
+                        if toggle {
+                            commands.entity(rotating_entity).insert(Paused);
+                        } else {
+                            commands.entity(rotating_entity).remove::<Paused>();
+                        }
+                    }
+                }
+            });
+
+            ui.horizontal(|ui| {
+                if ui.add(Button::new("Add pen")).clicked() {
+                    commands.entity(rotating_entity).with_children(|gear| {
+                        gear.spawn(PenBundle {
+                            pen: Pen(0.5 * radius.0),
+                            ..default()
+                        });
+                    });
+                }
+
+                if ui.add(Button::new("Add gear")).clicked() {
+                    random.spawn_gear(
+                        commands,
+                        rotating_entity,
+                        (
+                            &Shape::Ring { radius: radius.0 },
+                            &gear_color,
+                            teeth.as_deref(),
+                        ),
+                        settings,
+                    );
+                }
+            });
+
+            // Gears rolling on this one
+            let carrier = Shape::Ring { radius: radius.0 };
+            let carrier_teeth = teeth.as_deref().copied();
+            for (i, &child) in gears.iter().enumerate() {
+                rotating_gear_ui(
+                    ui,
+                    commands,
+                    (q_rotating, q_pens),
+                    random,
+                    settings,
+                    child,
+                    &[path, &[i]].concat(),
+                    (&carrier, carrier_teeth),
+                    None,
+                );
+            }
+        });
+}
+
+/// Sidebar section of the pen `entity`, the `index`th of the gear at `path`
+fn pen_ui(
+    ui: &mut Ui,
+    commands: &mut Commands,
+    q_pens: &mut Pens,
+    settings: &Settings,
+    entity: Entity,
+    path: &[usize],
+    index: usize,
+) {
+    let (
+        pen_entity,
+        mut pen,
+        mut angle,
+        mut pen_width,
+        mut pen_style,
+        mut line_color,
+        mut line,
+        _sampler,
+        pen_up,
+    ) = r!(q_pens.get_mut(entity));
+
+    CollapsingHeader::new(format!("Pen #{}", index + 1))
+        .default_open(true)
+        .show(ui, |ui| {
+            Grid::new(format!("pen grid {path:?} {index}"))
+                .num_columns(2)
+                .spacing([40.0, 4.0])
+                .striped(true)
+                .show(ui, |ui| {
+                    ui.label("Distance");
+                    ui.add(DragValue::new(&mut pen.0).range(0.0..=128.0).speed(0.1));
+                    ui.end_row();
+
+                    ui.label("Angle");
+                    ui.drag_angle(&mut angle.0);
+                    ui.end_row();
+
+                    pen_style_editor(ui, &mut pen_width, &mut pen_style);
+
+                    ui.label("Line color");
+                    color_picker(ui, &mut line_color.0);
+                    ui.end_row();
+
+                    ui.label("Line length");
+                    ui.label(format!("{}", line.0.len()));
+                    ui.end_row();
+                });
+
+            // Pen controls
+            ui.horizontal(|ui| {
+                if ui.add(Button::new("Clear line")).clicked() {
+                    line.0 = Vec::new();
+                }
+
+                if ui.add(Button::new("Simplify")).clicked() {
+                    line.0 = simplify(&line.0, settings.simplify_tolerance);
+                }
+
+                let mut draw = pen_up.is_none();
+                ui.toggle_value(&mut draw, "Draw");
+                if draw != pen_up.is_none() {
+                    if draw {
+                        commands.entity(pen_entity).remove::<PenUp>();
+                    } else {
+                        // Start over when the pen comes back down rather than joining up with
+                        // where it was lifted
+                        line.0 = Vec::new();
+                        commands.entity(pen_entity).insert(PenUp);
+                    }
+                }
+
+                if ui.add(Button::new("Remove pen")).clicked() {
+                    commands.trigger(Remove(pen_entity));
+                }
+            });
+        });
+}
+
 fn update_cursor_icon(mut contexts: EguiContexts, cursor: Res<Cursor>) {
     if let Some(cursor_icon) = cursor.0 {
         let ctx = contexts.ctx_mut();
@@ -235,7 +758,333 @@ fn update_cursor_icon(mut contexts: EguiContexts, cursor: Res<Cursor>) {
     }
 }
 
-fn color_picker(mut ui: &mut Ui, line_color: &mut Srgba) {
+/// Toggle and edit the optional tooth count of a gear, starting from `estimate` teeth
+fn teeth_editor(
+    ui: &mut Ui,
+    commands: &mut Commands,
+    entity: Entity,
+    teeth: Option<&mut Teeth>,
+    estimate: f32,
+) {
+    ui.label("Teeth");
+    ui.horizontal(|ui| {
+        let mut enabled = teeth.is_some();
+        ui.checkbox(&mut enabled, "");
+
+        match teeth {
+            Some(teeth) => {
+                ui.add(DragValue::new(&mut teeth.0).range(1..=512));
+                if !enabled {
+                    commands.entity(entity).remove::<Teeth>();
+                }
+            }
+            None if enabled => {
+                commands
+                    .entity(entity)
+                    .insert(Teeth(estimate.round().max(1.0) as u32));
+            }
+            None => {}
+        }
+    });
+    ui.end_row();
+}
+
+fn shape_editor(ui: &mut Ui, id: impl std::hash::Hash, shape: &mut Shape, resizable: bool) {
+    ui.label("Shape");
+    let mut name = shape.name();
+    ComboBox::from_id_source(id)
+        .selected_text(name)
+        .show_ui(ui, |ui| {
+            for option in Shape::NAMES {
+                ui.selectable_value(&mut name, option, option);
+            }
+        });
+    if name != shape.name() {
+        *shape = shape.with_name(name);
+    }
+    ui.end_row();
+
+    match shape {
+        Shape::Ring { radius } => {
+            ui.label("Radius");
+            ui.add_enabled(
+                resizable,
+                DragValue::new(radius).range(1.0..=128.0).speed(0.1),
+            );
+            ui.end_row();
+        }
+        Shape::Ellipse { radii } => {
+            ui.label("Radii");
+            ui.horizontal(|ui| {
+                ui.add_enabled(
+                    resizable,
+                    DragValue::new(&mut radii.x).range(1.0..=256.0).speed(0.1),
+                );
+                ui.add_enabled(
+                    resizable,
+                    DragValue::new(&mut radii.y).range(1.0..=256.0).speed(0.1),
+                );
+            });
+            ui.end_row();
+        }
+        Shape::Bar { size, corner } => {
+            ui.label("Size");
+            ui.horizontal(|ui| {
+                ui.add_enabled(
+                    resizable,
+                    DragValue::new(&mut size.x).range(1.0..=512.0).speed(0.1),
+                );
+                ui.add_enabled(
+                    resizable,
+                    DragValue::new(&mut size.y).range(1.0..=512.0).speed(0.1),
+                );
+            });
+            ui.end_row();
+
+            ui.label("Corner radius");
+            ui.add(
+                DragValue::new(corner)
+                    .range(0.0..=0.5 * size.min_element())
+                    .speed(0.1),
+            );
+            ui.end_row();
+        }
+        Shape::Triangle { radius, corner } => {
+            ui.label("Radius");
+            ui.add_enabled(
+                resizable,
+                DragValue::new(radius).range(1.0..=256.0).speed(0.1),
+            );
+            ui.end_row();
+
+            ui.label("Corner radius");
+            ui.add(DragValue::new(corner).range(0.0..=0.5 * *radius).speed(0.1));
+            ui.end_row();
+        }
+        Shape::Polygon { vertices, corner } => {
+            ui.label("Corner radius");
+            ui.add(DragValue::new(corner).range(0.0..=128.0).speed(0.1));
+            ui.end_row();
+
+            let mut remove = None;
+            for (i, vertex) in vertices.iter_mut().enumerate() {
+                ui.label(format!("Vertex #{}", i + 1));
+                ui.horizontal(|ui| {
+                    ui.add_enabled(
+                        resizable,
+                        DragValue::new(&mut vertex.x)
+                            .range(-256.0..=256.0)
+                            .speed(0.1),
+                    );
+                    ui.add_enabled(
+                        resizable,
+                        DragValue::new(&mut vertex.y)
+                            .range(-256.0..=256.0)
+                            .speed(0.1),
+                    );
+                    if ui.add(Button::new("Remove")).clicked() {
+                        remove = Some(i);
+                    }
+                });
+                ui.end_row();
+            }
+
+            // Keep at least a triangle
+            if let Some(i) = remove.filter(|_| vertices.len() > 3) {
+                vertices.remove(i);
+            }
+
+            ui.label("");
+            if ui.add(Button::new("Add vertex")).clicked() {
+                // Split the closing edge
+                let first = vertices.first().copied().unwrap_or_default();
+                let last = vertices.last().copied().unwrap_or_default();
+                vertices.push(first.lerp(last, 0.5));
+            }
+            ui.end_row();
+        }
+    }
+}
+
+fn pen_style_editor(ui: &mut Ui, pen_width: &mut PenWidth, pen_style: &mut PenStyle) {
+    ui.label("Pen width");
+    ui.add(
+        DragValue::new(&mut pen_width.0)
+            .range(0.1..=32.0)
+            .speed(0.05),
+    );
+    ui.end_row();
+
+    ui.label("Caps");
+    ui.horizontal(|ui| {
+        ui.selectable_value(&mut pen_style.cap, Cap::Butt, "Butt");
+        ui.selectable_value(&mut pen_style.cap, Cap::Square, "Square");
+        ui.selectable_value(&mut pen_style.cap, Cap::Round, "Round");
+    });
+    ui.end_row();
+
+    ui.label("Joins");
+    ui.horizontal(|ui| {
+        ui.selectable_value(&mut pen_style.join, Join::Miter, "Miter");
+        ui.selectable_value(&mut pen_style.join, Join::Bevel, "Bevel");
+        ui.selectable_value(&mut pen_style.join, Join::Round, "Round");
+    });
+    ui.end_row();
+}
+
+/// Show the seed a random gear was made from, with a button to use it for the next one
+fn seed_row(ui: &mut Ui, seed: Option<&Seed>, random: &mut Random) {
+    let Some(&Seed(seed)) = seed else {
+        return;
+    };
+
+    ui.label("Seed");
+    ui.horizontal(|ui| {
+        ui.label(seed.to_string());
+        if ui.add(Button::new("Reuse")).clicked() {
+            random.seed = seed;
+        }
+    });
+    ui.end_row();
+}
+
+/// Edit the bounds of a range within `limits`
+fn range_editor(ui: &mut Ui, label: &str, range: &mut Range<f32>, limits: RangeInclusive<f32>) {
+    ui.label(label);
+    ui.horizontal(|ui| {
+        ui.add(
+            DragValue::new(&mut range.start)
+                .range(*limits.start()..=range.end)
+                .speed(0.1),
+        );
+        ui.label("to");
+        ui.add(
+            DragValue::new(&mut range.end)
+                .range(range.start..=*limits.end())
+                .speed(0.1),
+        );
+    });
+    ui.end_row();
+}
+
+/// Edit the paper plotter exports are placed on
+fn page_editor(ui: &mut Ui, page: &mut Page) {
+    ui.label("Page size");
+    ui.horizontal(|ui| {
+        ui.add(
+            DragValue::new(&mut page.size.x)
+                .range(10.0..=2000.0)
+                .suffix(" mm"),
+        );
+        ui.label("×");
+        ui.add(
+            DragValue::new(&mut page.size.y)
+                .range(10.0..=2000.0)
+                .suffix(" mm"),
+        );
+    });
+    ui.end_row();
+
+    ui.label("Page margin");
+    ui.add(
+        DragValue::new(&mut page.margin)
+            .range(0.0..=0.5 * page.size.min_element())
+            .suffix(" mm"),
+    );
+    ui.end_row();
+
+    ui.label("Page scale");
+    ui.horizontal(|ui| {
+        let mut fit = page.scale.is_none();
+        if ui.checkbox(&mut fit, "Fit").changed() {
+            page.scale = if fit { None } else { Some(1.0) };
+        }
+        if let Some(scale) = &mut page.scale {
+            ui.add(
+                DragValue::new(scale)
+                    .range(0.001..=100.0)
+                    .speed(0.01)
+                    .suffix(" mm/unit"),
+            );
+        }
+    });
+    ui.end_row();
+}
+
+/// Pick the gears and pen hole of a kit combination
+fn kit_editor(ui: &mut Ui, kit: &mut Kit) {
+    ui.label("Ring");
+    ComboBox::from_id_source("kit ring")
+        .selected_text(format!("{} teeth", kit.ring))
+        .show_ui(ui, |ui| {
+            for teeth in RING_TEETH {
+                ui.selectable_value(&mut kit.ring, teeth, format!("{teeth} teeth"));
+            }
+        });
+    ui.end_row();
+
+    ui.label("Wheel");
+    ComboBox::from_id_source("kit wheel")
+        .selected_text(format!("{} teeth", kit.wheel))
+        .show_ui(ui, |ui| {
+            for teeth in WHEEL_TEETH {
+                ui.selectable_value(&mut kit.wheel, teeth, format!("{teeth} teeth"));
+            }
+        });
+    ui.end_row();
+
+    ui.label("Hole");
+    ui.add(DragValue::new(&mut kit.hole).range(1..=Kit::holes(kit.wheel).max(1)));
+    ui.end_row();
+}
+
+/// Edit the commands written to G-code exports
+fn gcode_editor(ui: &mut Ui, gcode: &mut GcodeSettings) {
+    ui.label("Pen up");
+    ui.text_edit_singleline(&mut gcode.pen_up);
+    ui.end_row();
+
+    ui.label("Pen down");
+    ui.text_edit_singleline(&mut gcode.pen_down);
+    ui.end_row();
+
+    ui.label("Pen change");
+    ui.text_edit_singleline(&mut gcode.tool_change);
+    ui.end_row();
+
+    ui.label("Feed rate");
+    ui.add(
+        DragValue::new(&mut gcode.feed_rate)
+            .range(1.0..=100000.0)
+            .suffix(" mm/min"),
+    );
+    ui.end_row();
+}
+
+/// Edit how HPGL exports line up with the plotter
+fn hpgl_editor(ui: &mut Ui, hpgl: &mut HpglSettings) {
+    ui.label("Plotter origin");
+    ui.horizontal(|ui| {
+        ui.add(DragValue::new(&mut hpgl.origin.x).suffix(" mm"));
+        ui.add(DragValue::new(&mut hpgl.origin.y).suffix(" mm"));
+    });
+    ui.end_row();
+
+    ui.label("Plotter rotation");
+    ui.add(
+        DragValue::new(&mut hpgl.rotation)
+            .range(-360.0..=360.0)
+            .speed(1.0)
+            .suffix("°"),
+    );
+    ui.end_row();
+
+    ui.label("Plotter pens");
+    ui.add(DragValue::new(&mut hpgl.pens).range(1..=64));
+    ui.end_row();
+}
+
+fn color_picker(ui: &mut Ui, line_color: &mut Srgba) {
     let [r, g, b, a] = line_color.to_f32_array();
     let mut egui_color: egui::Rgba = egui::Rgba::from_srgba_unmultiplied(
         (r * 255.0) as u8,
@@ -245,7 +1094,7 @@ fn color_picker(mut ui: &mut Ui, line_color: &mut Srgba) {
     );
 
     egui::widgets::color_picker::color_edit_button_rgba(
-        &mut ui,
+        ui,
         &mut egui_color,
         egui::color_picker::Alpha::Opaque,
     );